@@ -0,0 +1,47 @@
+// Runs without the unstable test harness: `cargo bench` prints the best of a few runs
+extern crate comp;
+
+use comp::scanner::{Scanner, TokenStream};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 10;
+
+// Roughly what the generated programs look like, repeated up to a few megabytes
+fn generated_program(min_len: usize) -> String {
+    let chunk = "/// counter\nvalue_1 = 0x2A; // answer\nprint(\"line\\n\");\nother_value = 1_000_000i64;\n/* block /* nested */ */\nprint(42);\n";
+    let mut program = String::with_capacity(min_len + chunk.len());
+    while program.len() < min_len {
+        program.push_str(chunk);
+    }
+
+    program
+}
+
+fn bench<F: FnMut() -> usize>(name: &str, bytes: usize, mut f: F) {
+    let mut best = Duration::from_secs(u64::MAX);
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        black_box(f());
+        best = best.min(start.elapsed());
+    }
+
+    let mb_per_s = bytes as f64 / (1024.0 * 1024.0) / best.as_secs_f64();
+    println!("{:<28} {:>10.2?} {:>8.1} MB/s", name, best, mb_per_s);
+}
+
+fn main() {
+    let program = generated_program(4 * 1024 * 1024);
+
+    bench("scan_4mb", program.len(), || Scanner::new("bench.txt", &program).count());
+
+    bench("scan_4mb_with_lookahead", program.len(), || {
+        let mut tokens = TokenStream::new(Scanner::new("bench.txt", &program));
+        let mut count = 0;
+        while tokens.peek(2).is_some() {
+            tokens.next();
+            count += 1;
+        }
+        count
+    });
+}
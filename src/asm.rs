@@ -1,13 +1,245 @@
 use asm_macro as Macro;
 use constdata::ConstData as ConstData;
+use interner::Symbol as Symbol;
+use tree::Operator as Operator;
+use symboltable::SymbolTable as SymbolTable;
+use symboltable::VariableType as VariableType;
+use symboltable::Global as Global;
+use symboltable::Structs as Structs;
+use symboltable;
+use asm_opcode::Condition as Condition;
+use diagnostic::Diagnostic as Diagnostic;
+use std::collections::HashMap;
+
+// A point in the code that can be jumped to, possibly before it's been placed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Label(usize);
+
+// Where break and continue go from inside a loop
+#[derive(Debug, Clone, Copy)]
+pub struct Loop {
+    pub name: Option<Symbol>,
+    pub continue_label: Label,
+    pub break_label: Label
+}
+
+// Where a function that returns an array or struct keeps the address it was given to copy it to
+const RETURN_POINTER: &str = "return pointer";
+
+// A user defined function: where its code starts and the types it takes and returns. Parameters
+// and results without a type annotation are i64s, which any scalar can stand in for.
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub label: Label,
+    pub params: Vec<Option<VariableType>>,
+    pub returns: Option<VariableType>
+}
+impl Function {
+    pub fn arity(&self) -> usize {
+        self.params.len()
+    }
+
+    // Arrays and structs are passed by address and copied by the callee. Returning one goes the
+    // way System V returns a struct in memory: the caller passes the address of a slot to copy it
+    // into as a hidden first argument, and gets that address back in RAX.
+    pub fn returns_by_pointer(&self) -> bool {
+        self.returns.is_some_and(|t| !t.is_scalar())
+    }
+}
 
 pub struct Assembler<'a> {
     pub output: Vec<u8>,
     pub length: u64,
-    pub const_data: &'a ConstData
+    pub const_data: &'a ConstData,
+    pub symbols: SymbolTable,
+    frame_size_patch: usize,
+    labels: Vec<Option<usize>>,
+    jumps: Vec<(usize, Label)>,
+    pub loops: Vec<Loop>,
+    pub functions: HashMap<Symbol, Function>,
+    pub return_label: Option<Label>,
+    pub return_type: Option<VariableType>,
+    pub globals: HashMap<Symbol, Global>,
+    pub structs: Structs,
+    // Semantic errors from statements that have been given up on, so the rest still get checked
+    pub errors: Vec<Diagnostic>
 }
 impl<'a> Assembler<'a> {
 
+    pub fn new(const_data: &'a ConstData, globals: HashMap<Symbol, Global>, structs: Structs) -> Assembler<'a> {
+        Assembler {
+            output: Vec::new(),
+            length: 0,
+            const_data,
+            symbols: SymbolTable::new(),
+            frame_size_patch: 0,
+            labels: Vec::new(),
+            jumps: Vec::new(),
+            loops: Vec::new(),
+            functions: HashMap::new(),
+            return_label: None,
+            return_type: None,
+            globals,
+            structs,
+            errors: Vec::new()
+        }
+    }
+
+    pub fn size_of(&self, var_type: VariableType) -> u64 {
+        symboltable::size_of(var_type, &self.structs)
+    }
+
+    pub fn new_label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    // Place the label at the next instruction
+    pub fn bind_label(&mut self, label: Label) {
+        self.labels[label.0] = Some(self.output.len());
+    }
+
+    pub fn jump(&mut self, label: Label) {
+        self.length += Macro::jump(&mut self.output);
+        self.jumps.push((self.output.len() - 4, label));
+    }
+
+    pub fn jump_if_false(&mut self, label: Label) {
+        self.length += Macro::jump_if_zero(&mut self.output);
+        self.jumps.push((self.output.len() - 4, label));
+    }
+
+    pub fn jump_if_true(&mut self, label: Label) {
+        self.length += Macro::jump_if_not_zero(&mut self.output);
+        self.jumps.push((self.output.len() - 4, label));
+    }
+
+    // Jump out of a counting loop once the variable reaches the end of its range
+    pub fn jump_unless_less(&mut self, variable: Symbol, end: Symbol, label: Label) {
+        let var_offset = self.symbols.get_var_index(variable);
+        let end_offset = self.symbols.get_var_index(end);
+        self.length += Macro::jump_unless_less(&mut self.output, var_offset, end_offset);
+        self.jumps.push((self.output.len() - 4, label));
+    }
+
+    pub fn increment_variable(&mut self, variable: Symbol) {
+        let offset = self.symbols.get_var_index(variable);
+        self.length += Macro::increment_variable(&mut self.output, offset);
+    }
+
+    // The innermost loop, or the innermost one with the given label
+    pub fn find_loop(&self, name: Option<Symbol>) -> Option<Loop> {
+        match name {
+            Some(_) => self.loops.iter().rev().find(|l| l.name == name).cloned(),
+            None => self.loops.last().cloned()
+        }
+    }
+
+    pub fn declare_function(&mut self, name: Symbol, params: Vec<Option<VariableType>>, returns: Option<VariableType>) {
+        let label = self.new_label();
+        self.functions.insert(name, Function {label, params, returns});
+    }
+
+    // Each function gets a frame and variables of its own, starting with its parameters. Every
+    // register is saved before any array or struct argument is copied, since copying uses them.
+    pub fn begin_function(&mut self, name: Symbol, params: &[Symbol]) {
+        let function = self.functions[&name].clone();
+        self.bind_label(function.label);
+        self.symbols = SymbolTable::new();
+        self.return_label = Some(self.new_label());
+        self.return_type = function.returns;
+        self.begin_frame();
+
+        let mut registers = 0..;
+        if function.returns_by_pointer() {
+            let offset = self.symbols.insert(Symbol::intern(RETURN_POINTER), VariableType::Integer, 8, false);
+            self.length += Macro::store_argument(&mut self.output, registers.next().unwrap(), offset);
+        }
+
+        let mut by_address = Vec::new();
+        for (&param, param_type) in params.iter().zip(function.params.iter()) {
+            let param_type = param_type.unwrap_or(VariableType::Integer);
+            let offset = if param_type.is_scalar() {
+                self.symbols.insert(param, param_type, 8, false)
+            } else {
+                let pointer = self.symbols.insert_temporary(8);
+                by_address.push((param, param_type, pointer));
+                pointer
+            };
+            self.length += Macro::store_argument(&mut self.output, registers.next().unwrap(), offset);
+        }
+
+        for (param, param_type, pointer) in by_address {
+            self.length += Macro::load_variable(&mut self.output, pointer);
+            self.declare_variable(param, param_type, false);
+        }
+    }
+
+    // The value being returned is in RAX. An array or struct goes to the caller's slot.
+    pub fn return_value(&mut self) {
+        match self.return_type {
+            Some(return_type) if !return_type.is_scalar() => {
+                let pointer = self.symbols.get_var_index(Symbol::intern(RETURN_POINTER));
+                let size = self.size_of(return_type);
+                self.length += Macro::copy_to_pointer(&mut self.output, pointer, size);
+            },
+            _ => {}
+        }
+    }
+
+    // Every return ends up here, with the result in RAX. An array or struct result is always
+    // handed back as the address of the caller's slot, whichever way the function ended.
+    pub fn end_function(&mut self) {
+        let return_label = self.return_label.take().unwrap();
+        self.bind_label(return_label);
+        if self.return_type.is_some_and(|t| !t.is_scalar()) {
+            let pointer = self.symbols.get_var_index(Symbol::intern(RETURN_POINTER));
+            self.length += Macro::load_variable(&mut self.output, pointer);
+        }
+        self.length += Macro::frame_teardown(&mut self.output);
+        self.end_frame();
+        self.return_type = None;
+    }
+
+    // The arguments are waiting on the stack, first one deepest
+    pub fn call(&mut self, name: Symbol, arg_count: usize) {
+        let label = self.functions[&name].label;
+        self.length += Macro::load_arguments(&mut self.output, arg_count);
+        self.length += Macro::call(&mut self.output);
+        self.jumps.push((self.output.len() - 4, label));
+    }
+
+    // Point every jump at its label now they've all been placed
+    pub fn resolve_jumps(&mut self) {
+        for &(patch, label) in &self.jumps {
+            let target = match self.labels[label.0] {
+                Some(target) => target,
+                None => panic!("jump to label {} which was never placed", label.0)
+            };
+            // Displacements are relative to the end of the jump instruction
+            let rel = target as i64 - (patch as i64 + 4);
+            self.output[patch..patch + 4].copy_from_slice(&(rel as i32).to_le_bytes());
+        }
+    }
+
+    // Sets up the stack frame variables live in; its size isn't known until end_frame()
+    pub fn begin_frame(&mut self) {
+        self.length += Macro::frame_setup(&mut self.output);
+        self.frame_size_patch = self.output.len() - 4;
+    }
+
+    // Bytes the current frame needs so far, keeping the stack 16 byte aligned
+    pub fn frame_size(&self) -> u64 {
+        self.symbols.get_length().div_ceil(16) * 16
+    }
+
+    // Fill in the frame size now every variable has a slot
+    pub fn end_frame(&mut self) {
+        let frame_size = self.frame_size();
+        let patch = self.frame_size_patch;
+        self.output[patch..patch + 4].copy_from_slice(&(frame_size as u32).to_le_bytes());
+    }
+
     pub fn exit(&mut self) {
         self.length += Macro::exit(&mut self.output);
     }
@@ -16,7 +248,7 @@ impl<'a> Assembler<'a> {
         self.length += Macro::print_str(&mut self.output, str_offset, str_len);
     }
 
-    pub fn builtin_function(&mut self, func_name: &str, param: &str) {
+    pub fn builtin_function(&mut self, func_name: &str, param: Symbol) {
         match func_name {
             "print" => {
                 let index = self.const_data.get_const_address(param);
@@ -27,14 +259,141 @@ impl<'a> Assembler<'a> {
         }
     }
 
-    // todo :)
-    #[allow(unused_variables)]
-    pub fn assignment_statement(&mut self, variable: &str, value: u64) {
-        let offset: u64 = self.const_data.get_end_of_section();
-        self.length += Macro::store_int(&mut self.output, offset, value);
+    // Print the value in RAX, formatted according to its type
+    pub fn print_value(&mut self, value_type: VariableType) {
+        self.length += match value_type {
+            VariableType::Integer => Macro::print_int(&mut self.output),
+            VariableType::Char    => Macro::print_char(&mut self.output),
+            VariableType::Bool    => Macro::print_bool(&mut self.output),
+            VariableType::Array(_) | VariableType::Struct(_) => panic!("arrays and structs can't be printed")
+        };
+    }
+
+    // Gives the variable a slot in the innermost scope and stores the value in RAX there
+    pub fn declare_variable(&mut self, variable: Symbol, value_type: VariableType, mutable: bool) {
+        let size = self.size_of(value_type);
+        self.symbols.insert(variable, value_type, size, mutable);
+        self.assignment_statement(variable);
+    }
+
+    // Stores the value in RAX. Arrays and structs are copied in from the address in RAX.
+    pub fn assignment_statement(&mut self, variable: Symbol) {
+        let offset: u64 = self.symbols.get_var_index(variable);
+        let var_type = self.symbols.get_var_type(variable);
+        if var_type.is_scalar() {
+            self.store_to_frame(offset);
+        } else {
+            let size = self.size_of(var_type);
+            self.copy_to_frame(offset, size);
+        }
+    }
+
+    pub fn load_static(&mut self, address: u64) {
+        self.length += Macro::load_static(&mut self.output, address);
+    }
+
+    pub fn store_static(&mut self, address: u64) {
+        self.length += Macro::store_static(&mut self.output, address);
+    }
+
+    // Loads the value into RAX, or the address for an array or struct
+    pub fn load_variable(&mut self, variable: Symbol) {
+        let offset: u64 = self.symbols.get_var_index(variable);
+        if self.symbols.get_var_type(variable).is_scalar() {
+            self.length += Macro::load_variable(&mut self.output, offset);
+        } else {
+            self.load_frame_address(offset);
+        }
+    }
+
+    pub fn store_to_frame(&mut self, offset: u64) {
+        self.length += Macro::store_variable(&mut self.output, offset);
+    }
+
+    pub fn load_frame_address(&mut self, offset: u64) {
+        self.length += Macro::load_frame_address(&mut self.output, offset);
+    }
+
+    pub fn fill_frame(&mut self, offset: u64, count: u64) {
+        self.length += Macro::fill_frame(&mut self.output, offset, count);
+    }
+
+    pub fn copy_to_frame(&mut self, offset: u64, size: u64) {
+        self.length += Macro::copy_to_frame(&mut self.output, offset, size);
+    }
+
+    pub fn copy_to_static(&mut self, address: u64, size: u64) {
+        self.length += Macro::copy_to_static(&mut self.output, address, size);
+    }
+
+    // Field offsets come from the struct's layout, and are relative to its address
+    pub fn load_field(&mut self, offset: u64, size: u64) {
+        self.length += Macro::load_field(&mut self.output, offset, size);
+    }
+
+    pub fn field_address(&mut self, offset: u64) {
+        self.length += Macro::field_address(&mut self.output, offset);
+    }
+
+    pub fn store_field(&mut self, offset: u64, size: u64) {
+        self.length += Macro::store_field(&mut self.output, offset, size);
+    }
+
+    pub fn copy_to_field(&mut self, offset: u64, size: u64) {
+        self.length += Macro::copy_to_field(&mut self.output, offset, size);
+    }
+
+    pub fn load_element(&mut self) {
+        self.length += Macro::load_element(&mut self.output);
+    }
+
+    pub fn store_element(&mut self) {
+        self.length += Macro::store_element(&mut self.output);
+    }
+
+    pub fn bounds_check(&mut self, length: u64, message: &str) {
+        self.length += Macro::bounds_check(&mut self.output, length, message);
+    }
+
+    pub fn load_int(&mut self, value: u64) {
+        self.length += Macro::load_immediate(&mut self.output, value);
+    }
+
+    // Keep the last result around while the next one is worked out
+    pub fn push_result(&mut self) {
+        self.length += Macro::push_rax(&mut self.output);
+    }
+
+    // Combines the pushed result (left) with the one in RAX (right), leaving the answer in RAX
+    pub fn binary_op(&mut self, op: Operator) {
+        self.length += Macro::pop_left_operand(&mut self.output);
+        self.length += match op {
+            Operator::Add      => Macro::add(&mut self.output),
+            Operator::Subtract => Macro::subtract(&mut self.output),
+            Operator::Multiply => Macro::multiply(&mut self.output),
+            Operator::Divide   => Macro::divide(&mut self.output),
+            Operator::Modulus  => Macro::modulus(&mut self.output),
+            Operator::Exponent => Macro::power(&mut self.output),
+            Operator::Equal        => Macro::compare(&mut self.output, Condition::Equal),
+            Operator::NotEqual     => Macro::compare(&mut self.output, Condition::NotEqual),
+            Operator::Less         => Macro::compare(&mut self.output, Condition::Less),
+            Operator::LessEqual    => Macro::compare(&mut self.output, Condition::LessEqual),
+            Operator::Greater      => Macro::compare(&mut self.output, Condition::Greater),
+            Operator::GreaterEqual => Macro::compare(&mut self.output, Condition::GreaterEqual),
+            _ => panic!("{:?} is not a binary operator", op)
+        };
+    }
+
+    pub fn unary_op(&mut self, op: Operator) {
+        self.length += match op {
+            Operator::Negate => Macro::negate(&mut self.output),
+            Operator::Not    => Macro::not(&mut self.output),
+            _ => panic!("{:?} is not a unary operator", op)
+        };
     }
 
     pub fn get_length(&self) -> u64 {
+        debug_assert_eq!(self.length, self.output.len() as u64, "macro lengths out of step with their output");
         self.length
     }
 
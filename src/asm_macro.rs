@@ -18,17 +18,483 @@ pub fn print_str(output: &mut Vec<u8>, str_offset: u64, str_len: u64) -> u64 {
     32
 }
 
-pub fn store_int(output: &mut Vec<u8>, address: u64, value: u64) -> u64 {
+pub fn load_immediate(output: &mut Vec<u8>, value: u64) -> u64 {
     output.extend(op::mov_im(op::Register::RAX, value.to_be()).iter());
-    output.extend(op::mov_rax_to_offset(address.to_be()).iter());
 
-    20
+    10
+}
+
+pub fn push_rax(output: &mut Vec<u8>) -> u64 {
+    output.extend(op::push(op::Register::RAX).iter());
+
+    2
+}
+
+// Right operand is in RAX and the left one is on the stack; afterwards RAX is left, RCX is right
+pub fn pop_left_operand(output: &mut Vec<u8>) -> u64 {
+    output.extend(op::mov(op::Register::RCX, op::Register::RAX).iter());
+    output.extend(op::pop(op::Register::RAX).iter());
+
+    5
+}
+
+pub fn add(output: &mut Vec<u8>) -> u64 {
+    output.extend(op::add(op::Register::RAX, op::Register::RCX).iter());
+
+    3
+}
+
+pub fn subtract(output: &mut Vec<u8>) -> u64 {
+    output.extend(op::sub(op::Register::RAX, op::Register::RCX).iter());
+
+    3
 }
 
-#[allow(dead_code)]
-pub fn load_int(output: &mut Vec<u8>, address: u64) -> u64 {
+pub fn multiply(output: &mut Vec<u8>) -> u64 {
+    output.extend(op::imul(op::Register::RAX, op::Register::RCX).iter());
+
+    4
+}
+
+pub fn divide(output: &mut Vec<u8>) -> u64 {
+    output.extend(op::cqo().iter());
+    output.extend(op::idiv(op::Register::RCX).iter());
+
+    5
+}
+
+pub fn modulus(output: &mut Vec<u8>) -> u64 {
+    output.extend(op::cqo().iter());
+    output.extend(op::idiv(op::Register::RCX).iter());
+    output.extend(op::mov(op::Register::RAX, op::Register::RDX).iter());
+
+    8
+}
+
+// RAX to the power of RCX by repeated multiplication. Integer powers only, so a negative
+// exponent counts as zero and gives 1.
+pub fn power(output: &mut Vec<u8>) -> u64 {
+    output.extend(op::mov(op::Register::RBX, op::Register::RAX).iter());
+    output.extend(op::mov_im_32(op::Register::RDX, 0x0).iter());
+    output.extend(op::test(op::Register::RCX, op::Register::RCX).iter());
+    output.extend(op::cmovs(op::Register::RCX, op::Register::RDX).iter());
+    output.extend(op::mov_im_32(op::Register::RAX, 0x01000000).iter());
+    output.extend(op::jrcxz(6).iter());                                // skip the loop
+    output.extend(op::imul(op::Register::RAX, op::Register::RBX).iter());
+    output.extend(op::loop_rel(-6).iter());                            // back to the imul
+
+    28
+}
+
+pub fn negate(output: &mut Vec<u8>) -> u64 {
+    output.extend(op::neg(op::Register::RAX).iter());
+
+    3
+}
+
+// Load from / store to an absolute address, where statics live
+pub fn load_static(output: &mut Vec<u8>, address: u64) -> u64 {
     output.extend(op::mov_offset_to_rax(address.to_be()).iter());
 
     10
 }
 
+pub fn store_static(output: &mut Vec<u8>, address: u64) -> u64 {
+    output.extend(op::mov_rax_to_offset(address.to_be()).iter());
+
+    10
+}
+
+
+// push rbp; mov rbp, rsp; sub rsp, <frame size>. The frame size is left as zero for the caller
+// to patch in once it knows how many variables there are; it's the last 4 bytes.
+pub fn frame_setup(output: &mut Vec<u8>) -> u64 {
+    output.extend(op::push(op::Register::RBP).iter());
+    output.extend(op::mov(op::Register::RBP, op::Register::RSP).iter());
+    output.extend(op::sub_im_32(op::Register::RSP, 0).iter());
+
+    12
+}
+
+// mov rsp, rbp; pop rbp; ret, undoing frame_setup on the way out of a function
+pub fn frame_teardown(output: &mut Vec<u8>) -> u64 {
+    output.extend(op::mov(op::Register::RSP, op::Register::RBP).iter());
+    output.extend(op::pop(op::Register::RBP).iter());
+    output.extend(op::ret().iter());
+
+    6
+}
+
+// Arguments are passed in registers the way the System V ABI does it, so only the first six fit
+pub const MAX_ARGUMENTS: usize = 6;
+
+// Variables and fields are addressed with 32-bit displacements, so a frame has to stay within reach
+pub const MAX_FRAME_SIZE: u64 = i32::MAX as u64;
+
+fn argument_register(index: usize) -> op::Register {
+    match index {
+        0 => op::Register::RDI,
+        1 => op::Register::RSI,
+        2 => op::Register::RDX,
+        3 => op::Register::RCX,
+        4 => op::Register::R8,
+        5 => op::Register::R9,
+        _ => panic!("only {} arguments can be passed in registers", MAX_ARGUMENTS)
+    }
+}
+
+// Arguments were pushed first to last, so the last one is on top of the stack
+pub fn load_arguments(output: &mut Vec<u8>, count: usize) -> u64 {
+    for index in (0..count).rev() {
+        output.extend(op::pop(argument_register(index)).iter());
+    }
+
+    2 * count as u64
+}
+
+// Copy an argument from its register into the callee's stack frame
+pub fn store_argument(output: &mut Vec<u8>, index: usize, offset: u64) -> u64 {
+    output.extend(op::mov_to_frame(offset as u32, argument_register(index)).iter());
+
+    7
+}
+
+// Call with the displacement left as zero, to be patched like a jump
+pub fn call(output: &mut Vec<u8>) -> u64 {
+    output.extend(op::call(0).iter());
+
+    5
+}
+
+pub fn load_variable(output: &mut Vec<u8>, offset: u64) -> u64 {
+    output.extend(op::mov_from_frame(op::Register::RAX, offset as u32).iter());
+
+    7
+}
+
+pub fn store_variable(output: &mut Vec<u8>, offset: u64) -> u64 {
+    output.extend(op::mov_to_frame(offset as u32, op::Register::RAX).iter());
+
+    7
+}
+
+// Jumps leave their displacement as zero, it's the last 4 bytes for the caller to patch
+pub fn jump(output: &mut Vec<u8>) -> u64 {
+    output.extend(op::jmp(0).iter());
+
+    5
+}
+
+// Jump if RAX is zero, i.e. the condition just worked out was false
+pub fn jump_if_zero(output: &mut Vec<u8>) -> u64 {
+    output.extend(op::cmp_im(op::Register::RAX, 0).iter());
+    output.extend(op::je(0).iter());
+
+    10
+}
+
+pub fn jump_if_not_zero(output: &mut Vec<u8>) -> u64 {
+    output.extend(op::cmp_im(op::Register::RAX, 0).iter());
+    output.extend(op::jne(0).iter());
+
+    10
+}
+
+// RAX = 1 if the condition holds between RAX and RCX, otherwise 0. The mov leaves the flags alone.
+pub fn compare(output: &mut Vec<u8>, cond: op::Condition) -> u64 {
+    output.extend(op::cmp(op::Register::RAX, op::Register::RCX).iter());
+    output.extend(op::mov_im_32(op::Register::RAX, 0x0).iter());
+    output.extend(op::setcc(cond, op::Register::RAX).iter());
+
+    12
+}
+
+// Flip a bool between 0 and 1
+pub fn not(output: &mut Vec<u8>) -> u64 {
+    output.extend(op::xor_im(op::Register::RAX, 1).iter());
+
+    4
+}
+
+// Jump unless the variable at var_offset is less than the one at end_offset, for counting loops
+pub fn jump_unless_less(output: &mut Vec<u8>, var_offset: u64, end_offset: u64) -> u64 {
+    output.extend(op::mov_from_frame(op::Register::RAX, var_offset as u32).iter());
+    output.extend(op::mov_from_frame(op::Register::RCX, end_offset as u32).iter());
+    output.extend(op::cmp(op::Register::RAX, op::Register::RCX).iter());
+    output.extend(op::jcc(op::Condition::GreaterEqual, 0).iter());
+
+    23
+}
+
+pub fn increment_variable(output: &mut Vec<u8>, offset: u64) -> u64 {
+    output.extend(op::mov_from_frame(op::Register::RAX, offset as u32).iter());
+    output.extend(op::inc(op::Register::RAX).iter());
+    output.extend(op::mov_to_frame(offset as u32, op::Register::RAX).iter());
+
+    17
+}
+
+// RAX = RBP - offset, the address of an array or struct in the stack frame
+pub fn load_frame_address(output: &mut Vec<u8>, offset: u64) -> u64 {
+    output.extend(op::lea_from_frame(op::Register::RAX, offset as u32).iter());
+
+    7
+}
+
+// Store RAX in each of count slots starting at RBP - offset
+pub fn fill_frame(output: &mut Vec<u8>, offset: u64, count: u64) -> u64 {
+    output.extend(op::lea_from_frame(op::Register::RDI, offset as u32).iter());
+    output.extend(op::mov_im(op::Register::RCX, count.to_be()).iter());
+    output.extend(op::rep_stosq().iter());
+
+    20
+}
+
+// The copies below take size bytes from the address in RAX, which they leave alone
+
+// Copy to RBP - offset
+pub fn copy_to_frame(output: &mut Vec<u8>, offset: u64, size: u64) -> u64 {
+    output.extend(op::mov(op::Register::RSI, op::Register::RAX).iter());
+    output.extend(op::lea_from_frame(op::Register::RDI, offset as u32).iter());
+    output.extend(op::mov_im(op::Register::RCX, size.to_be()).iter());
+    output.extend(op::rep_movsb().iter());
+
+    22
+}
+
+// Copy to a fixed address
+pub fn copy_to_static(output: &mut Vec<u8>, address: u64, size: u64) -> u64 {
+    output.extend(op::mov(op::Register::RSI, op::Register::RAX).iter());
+    output.extend(op::mov_im(op::Register::RDI, address.to_be()).iter());
+    output.extend(op::mov_im(op::Register::RCX, size.to_be()).iter());
+    output.extend(op::rep_movsb().iter());
+
+    25
+}
+
+// Copy to the address held at RBP - offset
+pub fn copy_to_pointer(output: &mut Vec<u8>, offset: u64, size: u64) -> u64 {
+    output.extend(op::mov(op::Register::RSI, op::Register::RAX).iter());
+    output.extend(op::mov_from_frame(op::Register::RDI, offset as u32).iter());
+    output.extend(op::mov_im(op::Register::RCX, size.to_be()).iter());
+    output.extend(op::rep_movsb().iter());
+
+    22
+}
+
+// Copy to a field offset bytes into the struct whose address is on the stack
+pub fn copy_to_field(output: &mut Vec<u8>, offset: u64, size: u64) -> u64 {
+    let start = output.len();
+    output.extend(op::mov(op::Register::RSI, op::Register::RAX).iter());
+    output.extend(op::pop(op::Register::RDI).iter());
+    output.extend(op::lea_from_pointer(op::Register::RDI, op::Register::RDI, offset as i32).iter());
+    output.extend(op::mov_im(op::Register::RCX, size.to_be()).iter());
+    output.extend(op::rep_movsb().iter());
+
+    (output.len() - start) as u64
+}
+
+// RAX = the field offset bytes into the struct at RAX, zero extended from its size
+pub fn load_field(output: &mut Vec<u8>, offset: u64, size: u64) -> u64 {
+    let code = match size {
+        8 => op::mov_from_pointer(op::Register::RAX, op::Register::RAX, offset as i32),
+        4 => op::mov_32_from_pointer(op::Register::RAX, op::Register::RAX, offset as i32),
+        1 => op::movzx_byte_from_pointer(op::Register::RAX, op::Register::RAX, offset as i32),
+        _ => panic!("can't load a {} byte field", size)
+    };
+    output.extend(code.iter());
+
+    code.len() as u64
+}
+
+// RAX = the address of a field that's an array or struct itself
+pub fn field_address(output: &mut Vec<u8>, offset: u64) -> u64 {
+    output.extend(op::lea_from_pointer(op::Register::RAX, op::Register::RAX, offset as i32).iter());
+
+    7
+}
+
+// The struct's address is on the stack, and the low size bytes of RAX go in the field
+pub fn store_field(output: &mut Vec<u8>, offset: u64, size: u64) -> u64 {
+    let start = output.len();
+    output.extend(op::pop(op::Register::RCX).iter());
+    let code = match size {
+        8 => op::mov_to_pointer(op::Register::RCX, offset as i32, op::Register::RAX),
+        4 => op::mov_32_to_pointer(op::Register::RCX, offset as i32, op::Register::RAX),
+        1 => op::mov_byte_to_pointer(op::Register::RCX, offset as i32, op::Register::RAX),
+        _ => panic!("can't store a {} byte field", size)
+    };
+    output.extend(code.iter());
+
+    (output.len() - start) as u64
+}
+
+// The array's address is on the stack and the index in RAX
+pub fn load_element(output: &mut Vec<u8>) -> u64 {
+    output.extend(op::mov(op::Register::RCX, op::Register::RAX).iter());
+    output.extend(op::pop(op::Register::RAX).iter());
+    output.extend(op::mov_from_indexed(op::Register::RAX, op::Register::RAX, op::Register::RCX).iter());
+
+    9
+}
+
+// The array's address and then the index are on the stack, the value is in RAX
+pub fn store_element(output: &mut Vec<u8>) -> u64 {
+    output.extend(op::pop(op::Register::RCX).iter());
+    output.extend(op::pop(op::Register::RDX).iter());
+    output.extend(op::mov_to_indexed(op::Register::RDX, op::Register::RCX, op::Register::RAX).iter());
+
+    8
+}
+
+// Carry on if the index in RAX is below length, otherwise write message to stderr and exit with
+// 101. Unsigned, so negative indexes fail too. The message sits in the code after the exit.
+pub fn bounds_check(output: &mut Vec<u8>, length: u64, message: &str) -> u64 {
+    let start = output.len();
+
+    let mut after_lea = Vec::new();
+    after_lea.extend(op::mov_im(op::Register::RDX, (message.len() as u64).to_be()).iter());
+    after_lea.extend(op::syscall().iter());
+    after_lea.extend(op::mov_im(op::Register::RAX, 0x3c00000000000000).iter());   // exit
+    after_lea.extend(op::mov_im(op::Register::RDI, 0x6500000000000000).iter());   // with 101
+    after_lea.extend(op::syscall().iter());
+
+    let mut failure = Vec::new();
+    failure.extend(op::mov_im_32(op::Register::RAX, 0x01000000).iter());   // write
+    failure.extend(op::mov_im_32(op::Register::RDI, 0x02000000).iter());   // to stderr
+    failure.extend(op::lea_rip(op::Register::RSI, after_lea.len() as i32).iter());
+    failure.extend(after_lea.iter());
+    failure.extend(message.as_bytes());
+
+    output.extend(op::mov_im(op::Register::RCX, length.to_be()).iter());
+    output.extend(op::cmp(op::Register::RAX, op::Register::RCX).iter());
+    output.extend(op::jcc(op::Condition::Below, failure.len() as i32).iter());
+    output.extend(failure.iter());
+
+    (output.len() - start) as u64
+}
+
+// The runtime print routines build their text backwards from R9 (the stack pointer on the way in)
+// into scratch space below the stack, with RSI tracking the first byte written
+fn print_buffer_setup(output: &mut Vec<u8>) {
+    output.extend(op::mov(op::Register::R9, op::Register::RSP).iter());
+    output.extend(op::sub_im(op::Register::RSP, 32).iter());
+    output.extend(op::mov(op::Register::RSI, op::Register::R9).iter());
+}
+
+fn print_buffer_write(output: &mut Vec<u8>) {
+    output.extend(op::mov(op::Register::RDX, op::Register::R9).iter());
+    output.extend(op::sub(op::Register::RDX, op::Register::RSI).iter());   // length
+    output.extend(op::mov_im_32(op::Register::RAX, 0x01000000).iter());   // write
+    output.extend(op::mov_im_32(op::Register::RDI, 0x01000000).iter());   // to stdout
+    output.extend(op::syscall().iter());
+    output.extend(op::mov(op::Register::RSP, op::Register::R9).iter());
+}
+
+fn print_buffer_push(output: &mut Vec<u8>, src: op::Register) {
+    output.extend(op::sub_im(op::Register::RSI, 1).iter());
+    output.extend(op::mov_byte_to_ptr(op::Register::RSI, src).iter());
+}
+
+// Print RAX as true or false
+pub fn print_bool(output: &mut Vec<u8>) -> u64 {
+    let start = output.len();
+    print_buffer_setup(output);
+
+    let mut true_text = Vec::new();
+    let mut false_text = Vec::new();
+    print_buffer_push_text(&mut true_text, "true");
+    print_buffer_push_text(&mut false_text, "false");
+    true_text.extend(op::jmp_short(false_text.len() as i8).iter());
+
+    output.extend(op::test(op::Register::RAX, op::Register::RAX).iter());
+    output.extend(op::jcc_short(op::Condition::Equal, true_text.len() as i8).iter());
+    output.extend(true_text.iter());
+    output.extend(false_text.iter());
+
+    print_buffer_write(output);
+
+    (output.len() - start) as u64
+}
+
+// Text known up front, pushed a byte at a time
+fn print_buffer_push_text(output: &mut Vec<u8>, text: &str) {
+    for byte in text.bytes().rev() {
+        output.extend(op::mov_im_32(op::Register::RDX, (byte as u32).to_be()).iter());
+        print_buffer_push(output, op::Register::RDX);
+    }
+}
+
+// Print RAX as a signed decimal number
+pub fn print_int(output: &mut Vec<u8>) -> u64 {
+    let start = output.len();
+
+    output.extend(op::mov(op::Register::R8, op::Register::RAX).iter());        // remember the sign
+    print_buffer_setup(output);
+    output.extend(op::mov_im_32(op::Register::RCX, 0x0a000000).iter());
+    output.extend(op::test(op::Register::RAX, op::Register::RAX).iter());
+    output.extend(op::jcc_short(op::Condition::NotSign, 3).iter());
+    output.extend(op::neg(op::Register::RAX).iter());
+
+    // Peel off digits, least significant first. Unsigned so that i64::MIN still works after neg.
+    let mut digit = Vec::new();
+    digit.extend(op::mov_im_32(op::Register::RDX, 0x0).iter());
+    digit.extend(op::div(op::Register::RCX).iter());
+    digit.extend(op::add_im(op::Register::RDX, b'0' as i8).iter());
+    print_buffer_push(&mut digit, op::Register::RDX);
+    digit.extend(op::test(op::Register::RAX, op::Register::RAX).iter());
+    let back = -((digit.len() + 2) as i8);
+    output.extend(digit.iter());
+    output.extend(op::jcc_short(op::Condition::NotEqual, back).iter());
+
+    let mut sign = Vec::new();
+    sign.extend(op::mov_im_32(op::Register::RDX, (b'-' as u32).to_be()).iter());
+    print_buffer_push(&mut sign, op::Register::RDX);
+    output.extend(op::test(op::Register::R8, op::Register::R8).iter());
+    output.extend(op::jcc_short(op::Condition::NotSign, sign.len() as i8).iter());
+    output.extend(sign.iter());
+
+    print_buffer_write(output);
+
+    (output.len() - start) as u64
+}
+
+// Print RAX as a char, UTF-8 encoding the code point
+pub fn print_char(output: &mut Vec<u8>) -> u64 {
+    let start = output.len();
+
+    print_buffer_setup(output);
+
+    // Continuation bytes carry 6 bits each; RCX is how much the lead byte has room for, which
+    // shrinks by a bit for every continuation byte
+    let mut multi_byte = Vec::new();
+    multi_byte.extend(op::mov_im_32(op::Register::RCX, 0x3f000000).iter());
+    let mut continuation = Vec::new();
+    continuation.extend(op::mov(op::Register::RDX, op::Register::RAX).iter());
+    continuation.extend(op::and_im(op::Register::RDX, 0x3f).iter());
+    continuation.extend(op::or_im(op::Register::RDX, -0x80).iter());
+    print_buffer_push(&mut continuation, op::Register::RDX);
+    continuation.extend(op::shr_im(op::Register::RAX, 6).iter());
+    continuation.extend(op::shr_im(op::Register::RCX, 1).iter());
+    continuation.extend(op::cmp(op::Register::RAX, op::Register::RCX).iter());
+    let back = -((continuation.len() + 2) as i8);
+    multi_byte.extend(continuation.iter());
+    multi_byte.extend(op::jcc_short(op::Condition::Above, back).iter());
+
+    // Lead byte marker is 0xFE - 2 * RCX: 0xC0, 0xE0 or 0xF0
+    multi_byte.extend(op::mov_im_32(op::Register::RDX, 0xfe000000).iter());
+    multi_byte.extend(op::sub(op::Register::RDX, op::Register::RCX).iter());
+    multi_byte.extend(op::sub(op::Register::RDX, op::Register::RCX).iter());
+    multi_byte.extend(op::add(op::Register::RAX, op::Register::RDX).iter());
+
+    // ASCII goes straight out as the one byte
+    output.extend(op::mov_im_32(op::Register::RCX, 0x80000000).iter());
+    output.extend(op::cmp(op::Register::RAX, op::Register::RCX).iter());
+    output.extend(op::jcc_short(op::Condition::Below, multi_byte.len() as i8).iter());
+    output.extend(multi_byte.iter());
+    print_buffer_push(output, op::Register::RAX);
+
+    print_buffer_write(output);
+
+    (output.len() - start) as u64
+}
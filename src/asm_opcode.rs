@@ -127,7 +127,6 @@ pub fn mov_rax_to_offset(offset: u64) -> [u8; 10] {
     opcode
 }
 
-#[allow(dead_code)]
 pub fn mov_offset_to_rax(offset: u64) -> [u8; 10] {
     let mov_op: u8 = 0xA1;
     let val_slice = get_val_slice(offset);
@@ -145,19 +144,341 @@ pub fn mov_offset_to_rax(offset: u64) -> [u8; 10] {
 pub fn syscall() -> [u8; 2] {
     [0x0F, 0x05]
 }
+
+// Call by rel bytes from the end of the instruction, the displacement being the last 4 bytes
+pub fn call(rel: i32) -> [u8; 5] {
+    let mut opcode = [0xE8, 0, 0, 0, 0];
+    opcode[1..5].copy_from_slice(&rel.to_le_bytes());
+
+    opcode
+}
+
+pub fn ret() -> [u8; 1] {
+    [0xC3]
+}
+
+// REX prefix for a 64-bit operation with `reg` in the ModRM reg field and `rm` in the r/m field
+fn get_rex_w(reg: &Register, rm: &Register) -> u8 {
+    0x48 + (get_rex_opcode_reg(reg) << 2) + get_rex_opcode_reg(rm)
+}
+
+// ModRM byte for register-direct operands
+fn get_modrm(reg: &Register, rm: &Register) -> u8 {
+    0xC0 + (get_register_operand(reg) << 3) + get_register_operand(rm)
+}
+
+// ModRM byte for instructions that only take one register, the reg field being an opcode extension
+fn get_modrm_ext(ext: u8, rm: &Register) -> u8 {
+    0xC0 + (ext << 3) + get_register_operand(rm)
+}
+
+pub fn mov(dest: Register, src: Register) -> [u8; 3] {
+    [get_rex_w(&src, &dest), 0x89, get_modrm(&src, &dest)]
+}
+
+pub fn push(reg: Register) -> [u8; 2] {
+    [0x40 + get_rex_opcode_reg(&reg), 0x50 + get_register_operand(&reg)]
+}
+
+pub fn pop(reg: Register) -> [u8; 2] {
+    [0x40 + get_rex_opcode_reg(&reg), 0x58 + get_register_operand(&reg)]
+}
+
+pub fn add(dest: Register, src: Register) -> [u8; 3] {
+    [get_rex_w(&src, &dest), 0x01, get_modrm(&src, &dest)]
+}
+
+pub fn sub(dest: Register, src: Register) -> [u8; 3] {
+    [get_rex_w(&src, &dest), 0x29, get_modrm(&src, &dest)]
+}
+
+// Signed multiply, dest *= src
+pub fn imul(dest: Register, src: Register) -> [u8; 4] {
+    [get_rex_w(&dest, &src), 0x0F, 0xAF, get_modrm(&dest, &src)]
+}
+
+// Sign-extend RAX into RDX:RAX, ahead of idiv
+pub fn cqo() -> [u8; 2] {
+    [0x48, 0x99]
+}
+
+// Signed divide RDX:RAX by src, quotient in RAX and remainder in RDX
+pub fn idiv(src: Register) -> [u8; 3] {
+    [get_rex_w(&Register::RAX, &src), 0xF7, get_modrm_ext(7, &src)]
+}
+
+pub fn neg(reg: Register) -> [u8; 3] {
+    [get_rex_w(&Register::RAX, &reg), 0xF7, get_modrm_ext(3, &reg)]
+}
+
+pub fn inc(reg: Register) -> [u8; 3] {
+    [get_rex_w(&Register::RAX, &reg), 0xFF, get_modrm_ext(0, &reg)]
+}
+
+#[allow(dead_code)]
+pub fn dec(reg: Register) -> [u8; 3] {
+    [get_rex_w(&Register::RAX, &reg), 0xFF, get_modrm_ext(1, &reg)]
+}
+
+// Sets flags on a & b
+pub fn test(a: Register, b: Register) -> [u8; 3] {
+    [get_rex_w(&b, &a), 0x85, get_modrm(&b, &a)]
+}
+
+// dest = src if the sign flag is set
+pub fn cmovs(dest: Register, src: Register) -> [u8; 4] {
+    [get_rex_w(&dest, &src), 0x0F, 0x48, get_modrm(&dest, &src)]
+}
+
+// Decrement RCX and jump by rel bytes (from the end of this instruction) if it's still non-zero
+pub fn loop_rel(rel: i8) -> [u8; 2] {
+    [0xE2, rel as u8]
+}
+
+// Jump by rel bytes if RCX is zero
+pub fn jrcxz(rel: i8) -> [u8; 2] {
+    [0xE3, rel as u8]
+}
+// Unsigned divide RDX:RAX by src, quotient in RAX and remainder in RDX
+pub fn div(src: Register) -> [u8; 3] {
+    [get_rex_w(&Register::RAX, &src), 0xF7, get_modrm_ext(6, &src)]
+}
+
+// Sets flags on a - b
+pub fn cmp(a: Register, b: Register) -> [u8; 3] {
+    [get_rex_w(&b, &a), 0x39, get_modrm(&b, &a)]
+}
+
+// Group 1 arithmetic with a sign-extended 8 bit immediate
+fn alu_im8(ext: u8, reg: &Register, val: i8) -> [u8; 4] {
+    [get_rex_w(&Register::RAX, reg), 0x83, get_modrm_ext(ext, reg), val as u8]
+}
+
+pub fn add_im(reg: Register, val: i8) -> [u8; 4] {
+    alu_im8(0, &reg, val)
+}
+
+pub fn or_im(reg: Register, val: i8) -> [u8; 4] {
+    alu_im8(1, &reg, val)
+}
+
+pub fn and_im(reg: Register, val: i8) -> [u8; 4] {
+    alu_im8(4, &reg, val)
+}
+
+pub fn sub_im(reg: Register, val: i8) -> [u8; 4] {
+    alu_im8(5, &reg, val)
+}
+
+pub fn xor_im(reg: Register, val: i8) -> [u8; 4] {
+    alu_im8(6, &reg, val)
+}
+
+// Sets flags on reg - val
+pub fn cmp_im(reg: Register, val: i8) -> [u8; 4] {
+    alu_im8(7, &reg, val)
+}
+
+// reg -= val, with a full 32 bit immediate (little-endian, unlike mov_im)
+pub fn sub_im_32(reg: Register, val: u32) -> [u8; 7] {
+    let mut opcode = [get_rex_w(&Register::RAX, &reg), 0x81, get_modrm_ext(5, &reg), 0, 0, 0, 0];
+    opcode[3..7].copy_from_slice(&val.to_le_bytes());
+
+    opcode
+}
+
+pub fn shr_im(reg: Register, val: u8) -> [u8; 4] {
+    [get_rex_w(&Register::RAX, &reg), 0xC1, get_modrm_ext(5, &reg), val]
+}
+
+// Store the low byte of src at the address in ptr. ptr can't be RSP, RBP, R12 or R13, which
+// need a different addressing form.
+pub fn mov_byte_to_ptr(ptr: Register, src: Register) -> [u8; 3] {
+    let rex = 0x40 + (get_rex_opcode_reg(&src) << 2) + get_rex_opcode_reg(&ptr);
+    [rex, 0x88, (get_register_operand(&src) << 3) + get_register_operand(&ptr)]
+}
+
+// Load from / store to [RBP - offset], the stack frame
+pub fn mov_from_frame(reg: Register, offset: u32) -> [u8; 7] {
+    let mut opcode = [get_rex_w(&reg, &Register::RBP), 0x8B, 0x85 + (get_register_operand(&reg) << 3), 0, 0, 0, 0];
+    opcode[3..7].copy_from_slice(&(offset as i32).wrapping_neg().to_le_bytes());
+
+    opcode
+}
+
+pub fn mov_to_frame(offset: u32, reg: Register) -> [u8; 7] {
+    let mut opcode = [get_rex_w(&reg, &Register::RBP), 0x89, 0x85 + (get_register_operand(&reg) << 3), 0, 0, 0, 0];
+    opcode[3..7].copy_from_slice(&(offset as i32).wrapping_neg().to_le_bytes());
+
+    opcode
+}
+
+// reg = RBP - offset, the address of something in the stack frame
+pub fn lea_from_frame(reg: Register, offset: u32) -> [u8; 7] {
+    let mut opcode = [get_rex_w(&reg, &Register::RBP), 0x8D, 0x85 + (get_register_operand(&reg) << 3), 0, 0, 0, 0];
+    opcode[3..7].copy_from_slice(&(offset as i32).wrapping_neg().to_le_bytes());
+
+    opcode
+}
+
+// reg = the address rel bytes on from the end of this instruction
+pub fn lea_rip(reg: Register, rel: i32) -> [u8; 7] {
+    let mut opcode = [get_rex_w(&reg, &Register::RAX), 0x8D, 0x05 + (get_register_operand(&reg) << 3), 0, 0, 0, 0];
+    opcode[3..7].copy_from_slice(&rel.to_le_bytes());
+
+    opcode
+}
+
+// Store RAX to [RDI] RCX times, moving RDI along
+pub fn rep_stosq() -> [u8; 3] {
+    [0xF3, 0x48, 0xAB]
+}
+
+// Copy RCX bytes from [RSI] to [RDI]
+pub fn rep_movsb() -> [u8; 2] {
+    [0xF3, 0xA4]
+}
+
+// REX and ModRM/SIB bytes for [base + index * 8]. base can't be RBP or R13, index can't be RSP.
+fn get_indexed_operand(reg: &Register, base: &Register, index: &Register) -> [u8; 3] {
+    let rex = 0x48 + (get_rex_opcode_reg(reg) << 2) + (get_rex_opcode_reg(index) << 1) + get_rex_opcode_reg(base);
+    let sib = 0xC0 + (get_register_operand(index) << 3) + get_register_operand(base);
+    [rex, 0x04 + (get_register_operand(reg) << 3), sib]
+}
+
+// dest = [base + index * 8]
+pub fn mov_from_indexed(dest: Register, base: Register, index: Register) -> [u8; 4] {
+    let operand = get_indexed_operand(&dest, &base, &index);
+    [operand[0], 0x8B, operand[1], operand[2]]
+}
+
+// [base + index * 8] = src
+pub fn mov_to_indexed(base: Register, index: Register, src: Register) -> [u8; 4] {
+    let operand = get_indexed_operand(&src, &base, &index);
+    [operand[0], 0x89, operand[1], operand[2]]
+}
+
+// REX prefix and ModRM byte for reg and [base + disp32]. base can't be RSP or R12, they'd need a SIB byte.
+fn get_pointer_operand(reg: &Register, base: &Register, wide: bool) -> [u8; 2] {
+    let rex = 0x40 + if wide { 0x08 } else { 0 } + (get_rex_opcode_reg(reg) << 2) + get_rex_opcode_reg(base);
+    [rex, 0x80 + (get_register_operand(reg) << 3) + get_register_operand(base)]
+}
+
+fn with_displacement(opcode: &[u8], disp: i32) -> Vec<u8> {
+    let mut bytes = opcode.to_vec();
+    bytes.extend_from_slice(&disp.to_le_bytes());
+    bytes
+}
+
+// dest = [base + disp], 8, 4 or 1 bytes wide. The narrower loads zero the rest of dest.
+pub fn mov_from_pointer(dest: Register, base: Register, disp: i32) -> Vec<u8> {
+    let operand = get_pointer_operand(&dest, &base, true);
+    with_displacement(&[operand[0], 0x8B, operand[1]], disp)
+}
+
+pub fn mov_32_from_pointer(dest: Register, base: Register, disp: i32) -> Vec<u8> {
+    let operand = get_pointer_operand(&dest, &base, false);
+    with_displacement(&[operand[0], 0x8B, operand[1]], disp)
+}
+
+pub fn movzx_byte_from_pointer(dest: Register, base: Register, disp: i32) -> Vec<u8> {
+    let operand = get_pointer_operand(&dest, &base, false);
+    with_displacement(&[operand[0], 0x0F, 0xB6, operand[1]], disp)
+}
+
+// [base + disp] = src, 8, 4 or 1 bytes of it
+pub fn mov_to_pointer(base: Register, disp: i32, src: Register) -> Vec<u8> {
+    let operand = get_pointer_operand(&src, &base, true);
+    with_displacement(&[operand[0], 0x89, operand[1]], disp)
+}
+
+pub fn mov_32_to_pointer(base: Register, disp: i32, src: Register) -> Vec<u8> {
+    let operand = get_pointer_operand(&src, &base, false);
+    with_displacement(&[operand[0], 0x89, operand[1]], disp)
+}
+
+pub fn mov_byte_to_pointer(base: Register, disp: i32, src: Register) -> Vec<u8> {
+    let operand = get_pointer_operand(&src, &base, false);
+    with_displacement(&[operand[0], 0x88, operand[1]], disp)
+}
+
+// dest = base + disp
+pub fn lea_from_pointer(dest: Register, base: Register, disp: i32) -> Vec<u8> {
+    let operand = get_pointer_operand(&dest, &base, true);
+    with_displacement(&[operand[0], 0x8D, operand[1]], disp)
+}
+
+#[allow(unused)]
+pub enum Condition {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Below,
+    BelowEqual,
+    Above,
+    AboveEqual,
+    Sign,
+    NotSign
+}
+
+fn get_condition_code(cond: &Condition) -> u8 {
+    match *cond {
+        Condition::Equal        => 0x4,
+        Condition::NotEqual     => 0x5,
+        Condition::Less         => 0xC,
+        Condition::LessEqual    => 0xE,
+        Condition::Greater      => 0xF,
+        Condition::GreaterEqual => 0xD,
+        Condition::Below        => 0x2,
+        Condition::BelowEqual   => 0x6,
+        Condition::Above        => 0x7,
+        Condition::AboveEqual   => 0x3,
+        Condition::Sign         => 0x8,
+        Condition::NotSign      => 0x9
+    }
+}
+
+// Set the low byte of reg to 1 if the condition holds, otherwise 0
+pub fn setcc(cond: Condition, reg: Register) -> [u8; 4] {
+    [0x40 + get_rex_opcode_reg(&reg), 0x0F, 0x90 + get_condition_code(&cond), 0xC0 + get_register_operand(&reg)]
+}
+
+pub fn jmp_short(rel: i8) -> [u8; 2] {
+    [0xEB, rel as u8]
+}
+
+// Conditional jump by rel bytes from the end of this instruction, for jumps within a macro
+pub fn jcc_short(cond: Condition, rel: i8) -> [u8; 2] {
+    [0x70 + get_condition_code(&cond), rel as u8]
+}
+
+// Near jumps by rel bytes from the end of the instruction. The displacement is always the last
+// 4 bytes, so jumps to code that hasn't been generated yet can be patched afterwards.
+pub fn jmp(rel: i32) -> [u8; 5] {
+    let mut opcode = [0xE9, 0, 0, 0, 0];
+    opcode[1..5].copy_from_slice(&rel.to_le_bytes());
+
+    opcode
+}
+
+pub fn jcc(cond: Condition, rel: i32) -> [u8; 6] {
+    let mut opcode = [0x0F, 0x80 + get_condition_code(&cond), 0, 0, 0, 0];
+    opcode[2..6].copy_from_slice(&rel.to_le_bytes());
+
+    opcode
+}
+
+pub fn je(rel: i32) -> [u8; 6] {
+    jcc(Condition::Equal, rel)
+}
+
+pub fn jne(rel: i32) -> [u8; 6] {
+    jcc(Condition::NotEqual, rel)
+}
 /*
-pub fn push(val: u64) -> u64 {unimplemented!()}
-pub fn pop(dest: u64) -> u64 {unimplemented!()}
-pub fn inc(reg: Register, val: u64) -> u64 {unimplemented!()}
-pub fn dec(reg: Register, val: u64) -> u64 {unimplemented!()}
-pub fn add(reg: Register, val: u64) -> u64 {unimplemented!()}
-pub fn sub(reg: Register, val: u64) -> u64 {unimplemented!()}
-pub fn mul(reg: Register, val: u64) -> u64 {unimplemented!()}
-pub fn div(reg: Register, val: u64) -> u64 {unimplemented!()}
 pub fn and(reg: Register, val: u64) -> u64 {unimplemented!()}
 pub fn or(reg: Register, val: u64) -> u64 {unimplemented!()}
-pub fn cmp(reg: Register, val: u64) -> u64 {unimplemented!()}
-pub fn jmp(reg: Register, val: u64) -> u64 {unimplemented!()}
-pub fn je(reg: Register, val: u64) -> u64 {unimplemented!()}
-pub fn jne(reg: Register, val: u64) -> u64 {unimplemented!()}
 */
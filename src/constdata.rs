@@ -1,11 +1,12 @@
 use std::collections::HashMap;
+use interner::Symbol as Symbol;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ConstData {
     data: Vec<u8>,
     length: u64,
-    indexes: HashMap<String, u64>,
-    lengths: HashMap<String, u64>,
+    indexes: HashMap<Symbol, u64>,
+    lengths: HashMap<Symbol, u64>,
     data_section_address: u64
 }
 impl ConstData
@@ -25,35 +26,54 @@ impl ConstData
         self.data_section_address += offset.to_le()
     }
 
-    pub fn insert(&mut self, constant: &str) -> (u64, u64) {
-        let str_bytes = constant.as_bytes();
+    pub fn insert(&mut self, constant: Symbol) -> (u64, u64) {
+        let str_bytes = constant.as_str().as_bytes();
         let str_length = str_bytes.len() as u64;
         let start_index = self.length;
         self.data.extend_from_slice(str_bytes);
         self.length += str_length;
-        self.indexes.insert(constant.to_string(), start_index);
-        self.lengths.insert(constant.to_string(), str_length);
+        self.indexes.insert(constant, start_index);
+        self.lengths.insert(constant, str_length);
 
         (self.length, str_length)
     }
 
+    // Room for a static variable, 8 byte aligned and holding its initial value. Returns its address.
+    pub fn insert_static(&mut self, value: u64) -> u64 {
+        self.insert_static_bytes(&value.to_le_bytes())
+    }
+
+    // The same for a static array or struct, already laid out as bytes
+    pub fn insert_static_bytes(&mut self, bytes: &[u8]) -> u64 {
+        let padding = (8 - self.length % 8) % 8;
+        self.data.extend(vec![0; padding as usize]);
+        self.length += padding;
+
+        let address = self.data_section_address + self.length;
+        self.data.extend_from_slice(bytes);
+        self.length += bytes.len() as u64;
+
+        address
+    }
+
     pub fn get_data(&self) -> &Vec<u8> {
         &self.data
     }
 
     #[allow(unused)]
-    pub fn get_const_index(&self, constant: &str) -> u64 {
-        *self.indexes.get(constant).unwrap()
+    pub fn get_const_index(&self, constant: Symbol) -> u64 {
+        *self.indexes.get(&constant).unwrap()
     }
 
-    pub fn get_const_length(&self, constant: &str) -> u64 {
-        *self.lengths.get(constant).unwrap()
+    pub fn get_const_length(&self, constant: Symbol) -> u64 {
+        *self.lengths.get(&constant).unwrap()
     }
 
-    pub fn get_const_address(&self, constant: &str) -> u64 {
-        self.data_section_address + *self.indexes.get(constant).unwrap()
+    pub fn get_const_address(&self, constant: Symbol) -> u64 {
+        self.data_section_address + *self.indexes.get(&constant).unwrap()
     }
 
+    #[allow(dead_code)]
     pub fn get_end_of_section(&self) -> u64 {
         self.data_section_address + self.length
     }
@@ -0,0 +1,24 @@
+use std::fmt;
+use span::Span as Span;
+
+// An error in the program and where it is. Syntax errors say what the parser was looking for and
+// what it got instead, errors found while generating code say what's wrong in their own words.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span:    Span
+}
+impl Diagnostic {
+    pub fn new(expected: &str, found: String, span: Span) -> Diagnostic {
+        Diagnostic {message: format!("expected {}, found {}", expected, found), span}
+    }
+
+    pub fn semantic(message: String, span: Span) -> Diagnostic {
+        Diagnostic {message, span}
+    }
+}
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.message)
+    }
+}
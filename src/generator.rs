@@ -1,24 +1,52 @@
 use tree::Node as Node;
 use tree::ASTNodeKind as ASTNodeKind;
+use tree::Operator as Operator;
 use elfwriter;
 use std::io;
 use std::fs::File as File;
 use bytewriter::ByteWriter;
 use asm::Assembler as Assembler;
+use asm::Loop as Loop;
+use asm_macro::MAX_ARGUMENTS as MAX_ARGUMENTS;
+use asm_macro::MAX_FRAME_SIZE as MAX_FRAME_SIZE;
 use constdata::ConstData as ConstData;
+use diagnostic::Diagnostic as Diagnostic;
+use interner::Symbol as Symbol;
+use span::Span as Span;
+use symboltable::VariableType as VariableType;
+use symboltable::Global as Global;
+use symboltable::Field as Field;
+use symboltable::StructLayout as StructLayout;
+use symboltable::Structs as Structs;
+use symboltable;
+use std::collections::HashMap;
 use std::str::FromStr;
 
-pub fn generate(ast: Vec<Node>, const_data: &ConstData, output_file: &str) {
+type GenResult<T> = Result<T, Diagnostic>;
+
+// Nothing is written unless the whole program is free of errors
+pub fn generate(ast: Vec<Node>, const_data: &ConstData, output_file: &str) -> Result<(), Vec<Diagnostic>> {
     // Traverse AST, output magic
 
-    // Write to asm file
-    let file = make_output_file(output_file);
-    if file.is_ok() {
+    // Structs, constants and statics are worked out first, statics taking their place in .data
+    let mut data = const_data.clone();
+    let mut errors = Vec::new();
+    let (globals, structs) = declare_globals(&ast, &mut data, &mut errors);
 
-        write_elf(&mut file.unwrap(), ast, const_data);
-    } else {
-        panic!("Couldn't write file");
+    let assembler = build_asm(ast, &data, globals, structs);
+    errors.extend(assembler.errors.iter().cloned());
+    if !errors.is_empty() {
+        errors.sort_by_key(|e| e.span.start);
+        return Err(errors);
     }
+
+    // Write to asm file
+    match make_output_file(output_file) {
+        Ok(mut file) => write_elf(&mut file, &assembler, &data),
+        Err(_) => panic!("Couldn't write file")
+    }
+
+    Ok(())
 }
 
 fn make_output_file(output_file: &str) -> Result<File, io::Error> {
@@ -26,58 +54,1063 @@ fn make_output_file(output_file: &str) -> Result<File, io::Error> {
     Ok(buff)
 }
 
-fn build_asm(ast: Vec<Node>, const_data: &ConstData) -> Assembler {
-    let mut asm = Assembler {output: Vec::new(), length: 0, const_data: const_data};
-    for nodes in ast {
-        walk_ast(nodes, &mut asm);
+fn error<T>(span: &Span, message: String) -> GenResult<T> {
+    Err(Diagnostic::semantic(message, span.clone()))
+}
+
+fn build_asm(ast: Vec<Node>, const_data: &ConstData, globals: HashMap<Symbol, Global>, structs: Structs) -> Assembler<'_> {
+    let mut asm = Assembler::new(const_data, globals, structs);
+
+    // Functions are known up front so they can be called before they're defined
+    let mut functions = Vec::new();
+    for node in &ast {
+        if node.kind == ASTNodeKind::FunctionDef {
+            match declare_function(node, &mut asm) {
+                Ok(()) => functions.push(node),
+                Err(e) => asm.errors.push(e)
+            }
+        }
+    }
+
+    // Top level statements run first, then the functions follow the exit
+    asm.begin_frame();
+    for node in &ast {
+        if !is_item(node) {
+            walk_ast(node, &mut asm);
+        }
     }
     asm.exit();
+    asm.end_frame();
+
+    for node in functions {
+        if let Err(e) = function_definition(node, &mut asm) {
+            asm.errors.push(e);
+        }
+    }
+    // A statement that failed part way through can leave its labels unplaced
+    if asm.errors.is_empty() {
+        asm.resolve_jumps();
+    }
 
     asm
 }
 
-fn walk_ast(ast: Node, asm: &mut Assembler) {
-    let mut operands: Vec<&Node> = Vec::new();
-    let traversal = ast.traverse_postorder();
-    for n in traversal {
-        match n.kind {
-            ASTNodeKind::FunctionCall => {
-                let func_name: String = n.val.clone().unwrap();
-                let func_param: String = operands.pop().unwrap().clone().val.unwrap();
+fn walk_ast(ast: &Node, asm: &mut Assembler) {
+    let frame_fitted = asm.frame_size() <= MAX_FRAME_SIZE;
+    if let Err(e) = statement(ast, asm) {
+        asm.errors.push(e);
+    }
+    // Only the statement that outgrew the frame is blamed
+    if frame_fitted && asm.frame_size() > MAX_FRAME_SIZE {
+        asm.errors.push(frame_too_big(&ast.span));
+    }
+}
+
+fn frame_too_big(span: &Span) -> Diagnostic {
+    Diagnostic::semantic(format!("variables can't take up more than {} bytes of stack", MAX_FRAME_SIZE), span.clone())
+}
+
+// Things declared at the top level rather than run
+fn is_item(n: &Node) -> bool {
+    matches!(n.kind, ASTNodeKind::FunctionDef | ASTNodeKind::StructDef | ASTNodeKind::Const | ASTNodeKind::Static(_))
+}
+
+// Structs, consts and statics in the order they're written, so each can use the ones above it
+fn declare_globals(ast: &Vec<Node>, data: &mut ConstData, errors: &mut Vec<Diagnostic>) -> (HashMap<Symbol, Global>, Structs) {
+    let mut globals = HashMap::new();
+    let mut structs = HashMap::new();
+
+    for n in ast {
+        let declared = match n.kind {
+            ASTNodeKind::StructDef => declare_struct(n, &globals, &mut structs),
+            ASTNodeKind::Const => declare_global(n, false, data, &mut globals, &structs),
+            ASTNodeKind::Static(mutable) => declare_global(n, mutable, data, &mut globals, &structs),
+            _ => continue
+        };
+        if let Err(e) = declared {
+            errors.push(e);
+        }
+    }
+
+    (globals, structs)
+}
+
+fn declare_global(n: &Node, mutable: bool, data: &mut ConstData, globals: &mut HashMap<Symbol, Global>,
+                  structs: &Structs) -> GenResult<()> {
+    let target = n.left.as_ref().unwrap();
+    let name: Symbol = target.val.unwrap();
+    let value = n.right.as_ref().unwrap();
+    let annotation = &n.children[0];
+
+    if globals.contains_key(&name) {
+        return error(&target.span, format!("{} is already declared", name));
+    }
+    let declared_type = variable_type(annotation, globals, structs)?;
+
+    // Arrays and structs always live in .data, even const ones, since they're used through
+    // their address
+    let global = if declared_type.is_scalar() {
+        let (result, value_type) = const_eval(value, globals)?;
+        expect_declared_type(value, name, declared_type, value_type)?;
+        if n.kind == ASTNodeKind::Const {
+            Global::Const(result as u64, value_type)
+        } else {
+            Global::Static {address: data.insert_static(result as u64), var_type: value_type, mutable}
+        }
+    } else {
+        let (bytes, value_type) = const_eval_bytes(value, globals, structs)?;
+        expect_declared_type(value, name, declared_type, value_type)?;
+        Global::Static {address: data.insert_static_bytes(&bytes), var_type: value_type, mutable}
+    };
+    globals.insert(name, global);
+
+    Ok(())
+}
+
+// Fields can only use types defined above, which also stops a struct containing itself
+fn declare_struct(n: &Node, globals: &HashMap<Symbol, Global>, structs: &mut Structs) -> GenResult<()> {
+    let name: Symbol = n.val.unwrap();
+    match name.as_str() {
+        "i64" | "char" | "bool" => return error(&n.span, format!("{} is a builtin type", name)),
+        _ if structs.contains_key(&name) => return error(&n.span, format!("struct {} is already defined", name)),
+        _ => {}
+    }
+
+    let mut fields: Vec<(Symbol, VariableType)> = Vec::new();
+    for field in &n.children {
+        let field_name: Symbol = field.val.unwrap();
+        if fields.iter().any(|&(f, _)| f == field_name) {
+            return error(&field.span, format!("{} already has a field called {}", name, field_name));
+        }
+        fields.push((field_name, variable_type(&field.children[0], globals, structs)?));
+    }
+
+    let layout = StructLayout::new(&fields, structs);
+    if layout.size > MAX_FRAME_SIZE {
+        return error(&n.span, format!("struct {} can't be bigger than {} bytes", name, MAX_FRAME_SIZE));
+    }
+    structs.insert(name, layout);
+
+    Ok(())
+}
+
+// Work out a constant expression at compile time, wrapping on overflow like the generated code
+fn const_eval(n: &Node, globals: &HashMap<Symbol, Global>) -> GenResult<(i64, VariableType)> {
+    match n.kind {
+        ASTNodeKind::Integer => Ok((scalar_value(n) as i64, VariableType::Integer)),
+        ASTNodeKind::Char => Ok((scalar_value(n) as i64, VariableType::Char)),
+        ASTNodeKind::Bool => Ok((scalar_value(n) as i64, VariableType::Bool)),
+        ASTNodeKind::Variable => {
+            match globals.get(&n.val.unwrap()) {
+                Some(&Global::Const(value, value_type)) => Ok((value as i64, value_type)),
+                Some(&Global::Static {..}) => error(&n.span, String::from("statics can't be read at compile time")),
+                None => error(&n.span, format!("{} isn't a constant declared above here", n.val.unwrap()))
+            }
+        },
+        ASTNodeKind::UnaryOp(op) => {
+            let operand = n.left.as_ref().unwrap();
+            let (value, value_type) = const_eval(operand, globals)?;
+            if op == Operator::Not {
+                expect_type(operand, value_type, VariableType::Bool)?;
+                Ok((value ^ 1, VariableType::Bool))
+            } else {
+                expect_number(operand, value_type)?;
+                Ok((value.wrapping_neg(), VariableType::Integer))
+            }
+        },
+        ASTNodeKind::BinaryOp(op) => {
+            let left = n.left.as_ref().unwrap();
+            let right = n.right.as_ref().unwrap();
+            let (a, left_type) = const_eval(left, globals)?;
+
+            // && and || don't look at the right hand side if the left decides it
+            if op == Operator::And || op == Operator::Or {
+                expect_type(left, left_type, VariableType::Bool)?;
+                if (op == Operator::And) == (a == 0) {
+                    return Ok((a, VariableType::Bool));
+                }
+                let (b, right_type) = const_eval(right, globals)?;
+                expect_type(right, right_type, VariableType::Bool)?;
+                return Ok((b, VariableType::Bool));
+            }
 
-                if is_function_builtin(&func_name) {
-                    asm.builtin_function(&func_name, &func_param);
+            let (b, right_type) = const_eval(right, globals)?;
+            if op.is_comparison() {
+                if left_type != right_type {
+                    return error(&n.span, format!("can't compare {} with {}", left_type, right_type));
                 }
-            },
-            // Assignment statements have the variable on the left (thus lowest of two stack entries)
-            ASTNodeKind::Assignment => {
-                let value: &str = &operands.pop().unwrap().clone().val.unwrap();
-                let variable: String = operands.pop().unwrap().clone().val.unwrap();
-                asm.assignment_statement(&variable, u64::from_str(value).unwrap());
+                let result = match op {
+                    Operator::Equal        => a == b,
+                    Operator::NotEqual     => a != b,
+                    Operator::Less         => a < b,
+                    Operator::LessEqual    => a <= b,
+                    Operator::Greater      => a > b,
+                    _                      => a >= b
+                };
+                return Ok((result as i64, VariableType::Bool));
+            }
+
+            expect_number(left, left_type)?;
+            expect_number(right, right_type)?;
+            if (op == Operator::Divide || op == Operator::Modulus) && b == 0 {
+                return error(&n.span, String::from("division by zero"));
+            }
+            let result = match op {
+                Operator::Add      => a.wrapping_add(b),
+                Operator::Subtract => a.wrapping_sub(b),
+                Operator::Multiply => a.wrapping_mul(b),
+                Operator::Divide   => a.wrapping_div(b),
+                Operator::Modulus  => a.wrapping_rem(b),
+                _                  => power(a, b)
+            };
+            Ok((result, VariableType::Integer))
+        },
+        _ => error(&n.span, String::from("this can't be worked out at compile time"))
+    }
+}
+
+// Square and multiply. A negative exponent counts as zero, the same as at runtime.
+fn power(base: i64, exponent: i64) -> i64 {
+    let mut result: i64 = 1;
+    let mut base = base;
+    let mut exponent = if exponent < 0 { 0 } else { exponent as u64 };
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = result.wrapping_mul(base);
+        }
+        base = base.wrapping_mul(base);
+        exponent >>= 1;
+    }
+
+    result
+}
+
+// An array or struct worked out at compile time, as the bytes it's made of in memory
+fn const_eval_bytes(n: &Node, globals: &HashMap<Symbol, Global>, structs: &Structs) -> GenResult<(Vec<u8>, VariableType)> {
+    match n.kind {
+        ASTNodeKind::ArrayLiteral => {
+            let mut bytes = Vec::new();
+            for element in &n.children {
+                let (value, value_type) = const_eval(element, globals)?;
+                expect_type(element, value_type, VariableType::Integer)?;
+                bytes.extend_from_slice(&(value as u64).to_le_bytes());
+            }
+            Ok((bytes, VariableType::Array(n.children.len() as u64)))
+        },
+        ASTNodeKind::ArrayRepeat => {
+            let element = n.left.as_ref().unwrap();
+            let (value, value_type) = const_eval(element, globals)?;
+            expect_type(element, value_type, VariableType::Integer)?;
+            let count = array_length(n.right.as_ref().unwrap(), globals)?;
+
+            let mut bytes = Vec::new();
+            for _ in 0..count {
+                bytes.extend_from_slice(&(value as u64).to_le_bytes());
+            }
+            Ok((bytes, VariableType::Array(count)))
+        },
+        ASTNodeKind::StructLiteral => {
+            let name: Symbol = n.val.unwrap();
+            let layout = struct_layout(n, structs)?;
+            let mut bytes = vec![0; layout.size as usize];
+
+            for (field, value) in struct_literal_fields(n, layout)? {
+                let (value_bytes, value_type) = const_eval_bytes(value, globals, structs)?;
+                expect_field_type(value, name, &field, value_type)?;
+                let offset = field.offset as usize;
+                bytes[offset..offset + value_bytes.len()].copy_from_slice(&value_bytes);
+            }
+            Ok((bytes, VariableType::Struct(name)))
+        },
+        _ => {
+            let (value, value_type) = const_eval(n, globals)?;
+            let size = symboltable::size_of(value_type, structs) as usize;
+            Ok(((value as u64).to_le_bytes()[..size].to_vec(), value_type))
+        }
+    }
+}
+
+// Lengths in array types and [value; count] have to be known at compile time
+fn array_length(n: &Node, globals: &HashMap<Symbol, Global>) -> GenResult<u64> {
+    let (length, length_type) = const_eval(n, globals)?;
+    expect_type(n, length_type, VariableType::Integer)?;
+    if length < 0 {
+        return error(&n.span, String::from("an array's length can't be negative"));
+    }
+
+    match (length as u64).checked_mul(8) {
+        Some(size) if size <= MAX_FRAME_SIZE => Ok(length as u64),
+        _ => error(&n.span, format!("an array can't be longer than {} elements", MAX_FRAME_SIZE / 8))
+    }
+}
+
+fn declare_function(n: &Node, asm: &mut Assembler) -> GenResult<()> {
+    let name: Symbol = n.val.unwrap();
+    if is_function_builtin(name.as_str()) {
+        return error(&n.span, format!("{} is a builtin function and can't be redefined", name));
+    }
+    if asm.functions.contains_key(&name) {
+        return error(&n.span, format!("function {} is already defined", name));
+    }
+
+    let mut params: Vec<Option<VariableType>> = Vec::new();
+    for p in &n.children {
+        params.push(match p.children.first() {
+            Some(t) => Some(variable_type(t, &asm.globals, &asm.structs)?),
+            None => None
+        });
+    }
+    let returns = match n.right.as_ref() {
+        Some(t) => Some(variable_type(t, &asm.globals, &asm.structs)?),
+        None => None
+    };
+
+    // Returning an array or struct takes up a register for the address to copy it to
+    let max_params = if returns.is_none_or(|t| t.is_scalar()) { MAX_ARGUMENTS } else { MAX_ARGUMENTS - 1 };
+    if params.len() > max_params {
+        return error(&n.span, format!("{} has {} parameters, but it can have at most {}", name, params.len(), max_params));
+    }
+
+    asm.declare_function(name, params, returns);
+    Ok(())
+}
+
+fn function_definition(n: &Node, asm: &mut Assembler) -> GenResult<()> {
+    let params: Vec<Symbol> = n.children.iter().map(|p| p.val.unwrap()).collect();
+    for (i, param) in params.iter().enumerate() {
+        if params[..i].contains(param) {
+            return error(&n.children[i].span, format!("{} is already a parameter of {}", param, n.val.unwrap()));
+        }
+    }
+
+    let body = n.left.as_ref().unwrap();
+    asm.begin_function(n.val.unwrap(), &params);
+    statement(body, asm)?;
+    asm.end_function();
+    if asm.frame_size() > MAX_FRAME_SIZE {
+        return Err(frame_too_big(&n.span));
+    }
+
+    // Falling off the end would hand back whatever happens to be in RAX
+    if n.right.is_some() && !always_returns(body) {
+        return error(&n.span, format!("not all paths in {} return a value", n.val.unwrap()));
+    }
+    Ok(())
+}
+
+// Whether every way through a statement ends in a return. Loops might not run at all.
+fn always_returns(n: &Node) -> bool {
+    match n.kind {
+        ASTNodeKind::Return => true,
+        ASTNodeKind::Block => n.children.iter().any(always_returns),
+        ASTNodeKind::If => n.children.len() == 2 && n.children.iter().all(always_returns),
+        _ => false
+    }
+}
+
+fn statement(n: &Node, asm: &mut Assembler) -> GenResult<()> {
+    match n.kind {
+        // Assignment statements have the variable on the left and the value on the right
+        ASTNodeKind::Let(mutable) => let_statement(n, mutable, asm),
+        ASTNodeKind::Assignment => assignment(n, asm),
+        ASTNodeKind::Const | ASTNodeKind::Static(_) =>
+            error(&n.span, String::from("constants and statics can only be declared at the top level")),
+        // Variables declared inside a block are gone once it ends. A statement with an error is
+        // given up on, but the ones after it are still checked.
+        ASTNodeKind::Block => {
+            asm.symbols.push_scope();
+            for stmt in &n.children {
+                if let Err(e) = statement(stmt, asm) {
+                    asm.errors.push(e);
+                }
+            }
+            asm.symbols.pop_scope();
+            Ok(())
+        },
+        ASTNodeKind::If => if_statement(n, asm),
+        ASTNodeKind::While => while_loop(n, asm),
+        ASTNodeKind::For => for_loop(n, asm),
+        ASTNodeKind::Break | ASTNodeKind::Continue => loop_jump(n, asm),
+        ASTNodeKind::FunctionDef => error(&n.span, String::from("functions can only be defined at the top level")),
+        ASTNodeKind::StructDef => error(&n.span, String::from("structs can only be defined at the top level")),
+        ASTNodeKind::Return => return_statement(n, asm),
+        // Anything else is an expression evaluated for its side effects
+        _ => expression(n, asm).map(|_| ())
+    }
+}
+
+// Leaves the value of the expression in RAX and returns its type
+fn expression(n: &Node, asm: &mut Assembler) -> GenResult<VariableType> {
+    match n.kind {
+        ASTNodeKind::FunctionCall => function_call(n, asm),
+        ASTNodeKind::BinaryOp(Operator::And) | ASTNodeKind::BinaryOp(Operator::Or) => logical_op(n, asm),
+        ASTNodeKind::BinaryOp(op) => {
+            // Left operand waits on the stack while the right one is worked out
+            let left = n.left.as_ref().unwrap();
+            let right = n.right.as_ref().unwrap();
+            let left_type = expression(left, asm)?;
+            asm.push_result();
+            let right_type = expression(right, asm)?;
+            asm.binary_op(op);
+
+            if op.is_comparison() {
+                if !left_type.is_scalar() || !right_type.is_scalar() {
+                    return error(&n.span, String::from("arrays and structs can't be compared"));
+                }
+                if left_type != right_type {
+                    return error(&n.span, format!("can't compare {} with {}", left_type, right_type));
+                }
+                if left_type == VariableType::Bool && op != Operator::Equal && op != Operator::NotEqual {
+                    return error(&n.span, String::from("bools can only be compared with == and !="));
+                }
+                Ok(VariableType::Bool)
+            } else {
+                expect_number(left, left_type)?;
+                expect_number(right, right_type)?;
+                Ok(VariableType::Integer)
+            }
+        },
+        ASTNodeKind::UnaryOp(op) => {
+            let operand = n.left.as_ref().unwrap();
+            let operand_type = expression(operand, asm)?;
+            asm.unary_op(op);
+
+            if op == Operator::Not {
+                expect_type(operand, operand_type, VariableType::Bool)?;
+                Ok(VariableType::Bool)
+            } else {
+                expect_number(operand, operand_type)?;
+                Ok(VariableType::Integer)
+            }
+        },
+        ASTNodeKind::Bool => {
+            asm.load_int(scalar_value(n));
+            Ok(VariableType::Bool)
+        },
+        ASTNodeKind::Integer => {
+            asm.load_int(scalar_value(n));
+            Ok(VariableType::Integer)
+        },
+        ASTNodeKind::Char => {
+            asm.load_int(scalar_value(n));
+            Ok(VariableType::Char)
+        },
+        ASTNodeKind::Variable => {
+            let variable: Symbol = n.val.unwrap();
+            if asm.symbols.contains(variable) {
+                asm.load_variable(variable);
+                return Ok(asm.symbols.get_var_type(variable));
+            }
+
+            match asm.globals.get(&variable).cloned() {
+                Some(Global::Const(value, value_type)) => {
+                    asm.load_int(value);
+                    Ok(value_type)
+                },
+                Some(Global::Static {address, var_type, ..}) => {
+                    if var_type.is_scalar() {
+                        asm.load_static(address);
+                    } else {
+                        asm.load_int(address);
+                    }
+                    Ok(var_type)
+                },
+                None => error(&n.span, format!("{} isn't declared", variable))
+            }
+        },
+        ASTNodeKind::ArrayLiteral => array_literal(n, asm),
+        ASTNodeKind::ArrayRepeat => array_repeat(n, asm),
+        ASTNodeKind::Index => {
+            indexed_array(n, asm)?;
+            asm.load_element();
+            Ok(VariableType::Integer)
+        },
+        ASTNodeKind::StructLiteral => struct_literal(n, asm),
+        ASTNodeKind::Field => {
+            let field = struct_field(n, asm)?;
+            if field.var_type.is_scalar() {
+                let size = asm.size_of(field.var_type);
+                asm.load_field(field.offset, size);
+            } else {
+                asm.field_address(field.offset);
+            }
+            Ok(field.var_type)
+        },
+        ASTNodeKind::String => error(&n.span, String::from("strings can only be printed")),
+        ASTNodeKind::Block | ASTNodeKind::If | ASTNodeKind::While | ASTNodeKind::For =>
+            error(&n.span, String::from("a block isn't a value")),
+        ASTNodeKind::Break | ASTNodeKind::Continue | ASTNodeKind::Return =>
+            error(&n.span, String::from("break, continue and return aren't values")),
+        ASTNodeKind::FunctionDef | ASTNodeKind::StructDef => error(&n.span, String::from("a definition isn't a value")),
+        ASTNodeKind::Assignment | ASTNodeKind::Let(_) | ASTNodeKind::Const | ASTNodeKind::Static(_) =>
+            error(&n.span, String::from("an assignment isn't a value")),
+        ASTNodeKind::Type => error(&n.span, String::from("a type isn't a value"))
+    }
+}
+
+// The elements are stored into a temporary in the frame, leaving its address in RAX
+fn array_literal(n: &Node, asm: &mut Assembler) -> GenResult<VariableType> {
+    let array_type = VariableType::Array(n.children.len() as u64);
+    let offset = asm.symbols.insert_temporary(asm.size_of(array_type));
+
+    for (i, element) in n.children.iter().enumerate() {
+        let element_type = expression(element, asm)?;
+        expect_type(element, element_type, VariableType::Integer)?;
+        asm.store_to_frame(offset - 8 * i as u64);
+    }
+    asm.load_frame_address(offset);
+
+    Ok(array_type)
+}
+
+// [value; count] is filled in at runtime, but the count has to be known up front
+fn array_repeat(n: &Node, asm: &mut Assembler) -> GenResult<VariableType> {
+    let element = n.left.as_ref().unwrap();
+    let count = array_length(n.right.as_ref().unwrap(), &asm.globals)?;
+    let array_type = VariableType::Array(count);
+    let offset = asm.symbols.insert_temporary(asm.size_of(array_type));
+
+    let element_type = expression(element, asm)?;
+    expect_type(element, element_type, VariableType::Integer)?;
+    asm.fill_frame(offset, count);
+    asm.load_frame_address(offset);
+
+    Ok(array_type)
+}
+
+// Leaves the array's address on the stack and the index in RAX, once it's been checked against
+// the length
+fn indexed_array(n: &Node, asm: &mut Assembler) -> GenResult<()> {
+    let base = n.left.as_ref().unwrap();
+    let index = n.right.as_ref().unwrap();
+
+    let length = match expression(base, asm)? {
+        VariableType::Array(length) => length,
+        other => return error(&base.span, format!("{} values can't be indexed", other))
+    };
+    asm.push_result();
+    let index_type = expression(index, asm)?;
+    expect_type(index, index_type, VariableType::Integer)?;
+    asm.bounds_check(length, &format!("{}: index out of bounds, the length is {}\n", n.span, length));
+    Ok(())
+}
+
+// The struct is zeroed first so its padding is too, then the fields are stored in the order
+// they're written. Leaves the address of the temporary it's built in in RAX.
+fn struct_literal(n: &Node, asm: &mut Assembler) -> GenResult<VariableType> {
+    let name: Symbol = n.val.unwrap();
+    let layout = struct_layout(n, &asm.structs)?.clone();
+    let offset = asm.symbols.insert_temporary(layout.size);
+    asm.load_int(0);
+    asm.fill_frame(offset, layout.size.div_ceil(8));
+
+    for (field, value) in struct_literal_fields(n, &layout)? {
+        asm.load_frame_address(offset);
+        asm.push_result();
+        let value_type = expression(value, asm)?;
+        expect_field_type(value, name, &field, value_type)?;
+        store_field(&field, asm);
+    }
+    asm.load_frame_address(offset);
+
+    Ok(VariableType::Struct(name))
+}
+
+fn struct_layout<'a>(n: &Node, structs: &'a Structs) -> GenResult<&'a StructLayout> {
+    match structs.get(&n.val.unwrap()) {
+        Some(layout) => Ok(layout),
+        None => error(&n.span, format!("there's no struct called {}", n.val.unwrap()))
+    }
+}
+
+// Pairs each value in a struct literal with its field. Every field has to be given exactly once.
+fn struct_literal_fields<'a>(n: &'a Node, layout: &StructLayout) -> GenResult<Vec<(Field, &'a Node)>> {
+    let name: Symbol = n.val.unwrap();
+    let mut fields: Vec<(Field, &Node)> = Vec::new();
+
+    for init in &n.children {
+        let field = match layout.field(init.val.unwrap()) {
+            Some(field) => field.clone(),
+            None => return error(&init.span, format!("{} has no field called {}", name, init.val.unwrap()))
+        };
+        if fields.iter().any(|(f, _)| f.name == field.name) {
+            return error(&init.span, format!("field {} is given more than once", field.name));
+        }
+        fields.push((field, init.left.as_ref().unwrap()));
+    }
 
-            },
-            ASTNodeKind::Integer => {
-                operands.push(n)
-            },
-            ASTNodeKind::Variable => {
-                operands.push(n)
+    for field in &layout.fields {
+        if !fields.iter().any(|(f, _)| f.name == field.name) {
+            return error(&n.span, format!("field {} of {} is missing", field.name, name));
+        }
+    }
+
+    Ok(fields)
+}
+
+fn expect_field_type(value: &Node, struct_name: Symbol, field: &Field, found: VariableType) -> GenResult<()> {
+    if field.var_type != found {
+        return error(&value.span, format!("{}.{} holds {} values, not {}", struct_name, field.name, field.var_type, found));
+    }
+    Ok(())
+}
+
+// Works out the struct on the left, leaving its address in RAX, and finds the field being used
+fn struct_field(n: &Node, asm: &mut Assembler) -> GenResult<Field> {
+    let base = n.left.as_ref().unwrap();
+    let name = match expression(base, asm)? {
+        VariableType::Struct(name) => name,
+        other => return error(&base.span, format!("{} values don't have fields", other))
+    };
+
+    match asm.structs[&name].field(n.val.unwrap()) {
+        Some(field) => Ok(field.clone()),
+        None => error(&n.span, format!("{} has no field called {}", name, n.val.unwrap()))
+    }
+}
+
+// The struct's address is on the stack and the value, or its address, in RAX
+fn store_field(field: &Field, asm: &mut Assembler) {
+    let size = asm.size_of(field.var_type);
+    if field.var_type.is_scalar() {
+        asm.store_field(field.offset, size);
+    } else {
+        asm.copy_to_field(field.offset, size);
+    }
+}
+
+// A variable, or an element or field of one
+fn assignment(n: &Node, asm: &mut Assembler) -> GenResult<()> {
+    let target = n.left.as_ref().unwrap();
+    match target.kind {
+        ASTNodeKind::Variable => variable_assignment(n, asm),
+        ASTNodeKind::Index => element_assignment(n, asm),
+        ASTNodeKind::Field => field_assignment(n, asm),
+        _ => error(&target.span, String::from("only variables and their elements and fields can be assigned to"))
+    }
+}
+
+// The variable that an element or field being assigned to belongs to
+fn assigned_variable(target: &Node) -> GenResult<&Node> {
+    match target.kind {
+        ASTNodeKind::Variable => Ok(target),
+        ASTNodeKind::Index | ASTNodeKind::Field => assigned_variable(target.left.as_ref().unwrap()),
+        _ => error(&target.span, String::from("only variables and their elements and fields can be assigned to"))
+    }
+}
+
+// Local variables shadow globals, and only mutable ones can be assigned to. Returns its type.
+fn mutable_variable(target: &Node, asm: &Assembler) -> GenResult<VariableType> {
+    let variable: Symbol = target.val.unwrap();
+    let (var_type, mutable) = if asm.symbols.contains(variable) {
+        (asm.symbols.get_var_type(variable), asm.symbols.is_mutable(variable))
+    } else {
+        match asm.globals.get(&variable).cloned() {
+            Some(Global::Static {var_type, mutable, ..}) => (var_type, mutable),
+            Some(Global::Const(..)) => return error(&target.span, format!("{} is a constant and can't be assigned to", variable)),
+            None => return error(&target.span, format!("{} isn't declared, use let to declare it", variable))
+        }
+    };
+
+    if !mutable {
+        return error(&target.span, format!("{} can't be assigned to, it wasn't declared with let mut or static mut", variable));
+    }
+
+    Ok(var_type)
+}
+
+fn variable_assignment(n: &Node, asm: &mut Assembler) -> GenResult<()> {
+    let target = n.left.as_ref().unwrap();
+    let variable: Symbol = target.val.unwrap();
+    let value_type = expression(n.right.as_ref().unwrap(), asm)?;
+
+    // A variable keeps the type it was declared with
+    let var_type = mutable_variable(target, asm)?;
+    if var_type != value_type {
+        return error(&target.span, format!("{} holds {} values, it can't be assigned {}", variable, var_type, value_type));
+    }
+
+    if asm.symbols.contains(variable) {
+        asm.assignment_statement(variable);
+    } else if let Some(Global::Static {address, ..}) = asm.globals.get(&variable).cloned() {
+        if var_type.is_scalar() {
+            asm.store_static(address);
+        } else {
+            let size = asm.size_of(var_type);
+            asm.copy_to_static(address, size);
+        }
+    }
+    Ok(())
+}
+
+// The array's address and the checked index wait on the stack while the value is worked out
+fn element_assignment(n: &Node, asm: &mut Assembler) -> GenResult<()> {
+    let target = n.left.as_ref().unwrap();
+    mutable_variable(assigned_variable(target)?, asm)?;
+
+    indexed_array(target, asm)?;
+    asm.push_result();
+    let value = n.right.as_ref().unwrap();
+    let value_type = expression(value, asm)?;
+    expect_type(value, value_type, VariableType::Integer)?;
+    asm.store_element();
+    Ok(())
+}
+
+// The struct's address waits on the stack while the value is worked out
+fn field_assignment(n: &Node, asm: &mut Assembler) -> GenResult<()> {
+    let target = n.left.as_ref().unwrap();
+    mutable_variable(assigned_variable(target)?, asm)?;
+
+    let field = struct_field(target, asm)?;
+    asm.push_result();
+    let value = n.right.as_ref().unwrap();
+    let value_type = expression(value, asm)?;
+    if field.var_type != value_type {
+        return error(&target.span, format!("{} holds {} values, it can't be assigned {}", field.name, field.var_type, value_type));
+    }
+    store_field(&field, asm);
+    Ok(())
+}
+
+// The value is worked out before the new variable exists, so let x = x + 1 in an inner scope
+// reads the outer x
+fn let_statement(n: &Node, mutable: bool, asm: &mut Assembler) -> GenResult<()> {
+    let target = n.left.as_ref().unwrap();
+    let variable: Symbol = target.val.unwrap();
+    if asm.symbols.declared_in_scope(variable) {
+        return error(&target.span, format!("{} is already declared in this scope", variable));
+    }
+
+    let declared_type = match n.children.first() {
+        Some(annotation) => Some(variable_type(annotation, &asm.globals, &asm.structs)?),
+        None => None
+    };
+    let value = n.right.as_ref().unwrap();
+    let value_type = match expression(value, asm) {
+        Ok(value_type) => value_type,
+        // It's declared anyway, so the uses of it further down don't complain that it isn't
+        Err(e) => {
+            asm.declare_variable(variable, declared_type.unwrap_or(VariableType::Integer), mutable);
+            return Err(e);
+        }
+    };
+
+    if let Some(declared_type) = declared_type {
+        expect_declared_type(value, variable, declared_type, value_type)?;
+    }
+
+    asm.declare_variable(variable, value_type, mutable);
+    Ok(())
+}
+
+// Array types keep their length expression as a child, and only hold i64s for now
+fn variable_type(n: &Node, globals: &HashMap<Symbol, Global>, structs: &Structs) -> GenResult<VariableType> {
+    if let Some(length) = n.children.first() {
+        if n.val.unwrap().as_str() != "i64" {
+            return error(&n.span, format!("arrays can only hold i64, not {}", n.val.unwrap()));
+        }
+        return Ok(VariableType::Array(array_length(length, globals)?));
+    }
+
+    match n.val.unwrap().as_str() {
+        "i64"  => Ok(VariableType::Integer),
+        "char" => Ok(VariableType::Char),
+        "bool" => Ok(VariableType::Bool),
+        _ if structs.contains_key(&n.val.unwrap()) => Ok(VariableType::Struct(n.val.unwrap())),
+        other  => error(&n.span, format!("there's no type called {}", other))
+    }
+}
+
+fn expect_declared_type(value: &Node, variable: Symbol, declared: VariableType, found: VariableType) -> GenResult<()> {
+    if declared != found {
+        return error(&value.span, format!("{} is declared as {}, but its value is {}", variable, declared, found));
+    }
+    Ok(())
+}
+
+// Only the left operand is evaluated if it decides the answer: false for && or true for ||.
+// Either way RAX ends up 0 or 1.
+fn logical_op(n: &Node, asm: &mut Assembler) -> GenResult<VariableType> {
+    let left = n.left.as_ref().unwrap();
+    let right = n.right.as_ref().unwrap();
+    let end = asm.new_label();
+
+    let left_type = expression(left, asm)?;
+    expect_type(left, left_type, VariableType::Bool)?;
+    match n.kind {
+        ASTNodeKind::BinaryOp(Operator::And) => asm.jump_if_false(end),
+        _ => asm.jump_if_true(end)
+    }
+    let right_type = expression(right, asm)?;
+    asm.bind_label(end);
+    expect_type(right, right_type, VariableType::Bool)?;
+
+    Ok(VariableType::Bool)
+}
+
+// Conditions of ifs and loops have to be Bools, there's no truthiness
+fn condition(n: &Node, asm: &mut Assembler) -> GenResult<()> {
+    let condition_type = expression(n, asm)?;
+    if condition_type != VariableType::Bool {
+        return error(&n.span, format!("a condition has to be a bool, not {}", condition_type));
+    }
+    Ok(())
+}
+
+fn expect_type(n: &Node, found: VariableType, expected: VariableType) -> GenResult<()> {
+    if found != expected {
+        return error(&n.span, format!("expected {}, found {}", expected, found));
+    }
+    Ok(())
+}
+
+// Arithmetic works on integers and chars' code points, but not on Bools or arrays
+fn expect_number(n: &Node, found: VariableType) -> GenResult<()> {
+    match found {
+        VariableType::Bool => error(&n.span, String::from("can't do arithmetic on a bool")),
+        VariableType::Array(_) => error(&n.span, String::from("can't do arithmetic on an array")),
+        VariableType::Struct(_) => error(&n.span, String::from("can't do arithmetic on a struct")),
+        _ => Ok(())
+    }
+}
+
+fn if_statement(n: &Node, asm: &mut Assembler) -> GenResult<()> {
+    let else_label = asm.new_label();
+    condition(n.left.as_ref().unwrap(), asm)?;
+    asm.jump_if_false(else_label);
+    statement(&n.children[0], asm)?;
+
+    match n.children.get(1) {
+        Some(else_branch) => {
+            let end_label = asm.new_label();
+            asm.jump(end_label);
+            asm.bind_label(else_label);
+            statement(else_branch, asm)?;
+            asm.bind_label(end_label);
+        },
+        None => asm.bind_label(else_label)
+    }
+    Ok(())
+}
+
+// Condition at the top, continue jumps back up to it and break jumps past the body
+fn while_loop(n: &Node, asm: &mut Assembler) -> GenResult<()> {
+    let top = asm.new_label();
+    let end = asm.new_label();
+
+    asm.bind_label(top);
+    condition(n.left.as_ref().unwrap(), asm)?;
+    asm.jump_if_false(end);
+
+    asm.loops.push(Loop {name: n.val, continue_label: top, break_label: end});
+    let body = statement(&n.children[0], asm);
+    asm.loops.pop();
+    body?;
+
+    asm.jump(top);
+    asm.bind_label(end);
+    Ok(())
+}
+
+// The loop variable and the end of the range live in a scope around the loop
+fn for_loop(n: &Node, asm: &mut Assembler) -> GenResult<()> {
+    asm.symbols.push_scope();
+    let result = range_loop(n, asm);
+    asm.symbols.pop_scope();
+
+    result
+}
+
+// Both ends are worked out once up front, before the loop variable hides anything they use, and
+// continue goes to the increment
+fn range_loop(n: &Node, asm: &mut Assembler) -> GenResult<()> {
+    let variable = n.left.as_ref().unwrap();
+    let range_start = Symbol::intern("for range start");
+    let range_end = Symbol::intern("for range end");
+
+    for &(bound, name) in [(&n.children[0], range_start), (&n.children[1], range_end)].iter() {
+        let bound_type = expression(bound, asm)?;
+        if bound_type != VariableType::Integer {
+            return error(&bound.span, format!("a range has to be made of integers, not {}", bound_type));
+        }
+        asm.declare_variable(name, VariableType::Integer, false);
+    }
+    asm.load_variable(range_start);
+    asm.declare_variable(variable.val.unwrap(), VariableType::Integer, false);
+
+    let top = asm.new_label();
+    let next = asm.new_label();
+    let end = asm.new_label();
+
+    asm.bind_label(top);
+    asm.jump_unless_less(variable.val.unwrap(), range_end, end);
+
+    asm.loops.push(Loop {name: n.val, continue_label: next, break_label: end});
+    let body = statement(&n.children[2], asm);
+    asm.loops.pop();
+    body?;
+
+    asm.bind_label(next);
+    asm.increment_variable(variable.val.unwrap());
+    asm.jump(top);
+    asm.bind_label(end);
+    Ok(())
+}
+
+fn loop_jump(n: &Node, asm: &mut Assembler) -> GenResult<()> {
+    let target = match (asm.find_loop(n.val), n.val) {
+        (Some(target), _) => target,
+        (None, Some(name)) => return error(&n.span, format!("there's no loop labelled '{} around this", name)),
+        (None, None) => return error(&n.span, format!("{} can only be used inside a loop",
+                                                      if n.kind == ASTNodeKind::Break { "break" } else { "continue" }))
+    };
+
+    match n.kind {
+        ASTNodeKind::Break => asm.jump(target.break_label),
+        _ => asm.jump(target.continue_label)
+    }
+    Ok(())
+}
+
+// Returns the type of the result, which is left in RAX. print leaves nothing useful there.
+fn function_call(n: &Node, asm: &mut Assembler) -> GenResult<VariableType> {
+    let callee = n.left.as_ref().unwrap();
+    if callee.kind != ASTNodeKind::Variable {
+        return error(&callee.span, String::from("only named functions can be called"));
+    }
+
+    let func_name: Symbol = callee.val.unwrap();
+    if is_function_builtin(func_name.as_str()) {
+        builtin_call(n, func_name, asm)?;
+        return Ok(VariableType::Integer);
+    }
+
+    let function = match asm.functions.get(&func_name) {
+        Some(function) => function.clone(),
+        None => return error(&callee.span, format!("there's no function called {}", func_name))
+    };
+    check_arity(n, func_name, function.arity())?;
+
+    // An array or struct result is copied into a slot of ours, whose address goes first
+    let result_slot = match function.returns {
+        Some(return_type) if function.returns_by_pointer() => {
+            let slot = asm.symbols.insert_temporary(asm.size_of(return_type));
+            asm.load_frame_address(slot);
+            asm.push_result();
+            Some(slot)
+        },
+        _ => None
+    };
+
+    for (arg, param_type) in n.children.iter().zip(function.params.iter()) {
+        let arg_type = expression(arg, asm)?;
+        match *param_type {
+            Some(param_type) if param_type != arg_type =>
+                return error(&arg.span, format!("{} takes {} here, not {}", func_name, param_type, arg_type)),
+            None if !arg_type.is_scalar() =>
+                return error(&arg.span, format!("{} values can only be passed to a parameter declared with their type", arg_type)),
+            _ => {}
+        }
+        asm.push_result();
+    }
+    asm.call(func_name, function.arity() + if result_slot.is_some() { 1 } else { 0 });
+
+    Ok(function.returns.unwrap_or(VariableType::Integer))
+}
+
+// The value has to match the declared return type. Functions without one return i64s, which
+// any scalar will do for.
+fn return_statement(n: &Node, asm: &mut Assembler) -> GenResult<()> {
+    let return_label = match asm.return_label {
+        Some(label) => label,
+        None => return error(&n.span, String::from("return can only be used inside a function"))
+    };
+
+    match (n.left.as_ref(), asm.return_type) {
+        (Some(value), return_type) => {
+            let value_type = expression(value, asm)?;
+            match return_type {
+                Some(return_type) if return_type != value_type =>
+                    return error(&value.span, format!("this function returns {}, not {}", return_type, value_type)),
+                None if !value_type.is_scalar() =>
+                    return error(&value.span, format!("a function has to declare that it returns {} values", value_type)),
+                _ => {}
+            }
+            asm.return_value();
+        },
+        (None, Some(return_type)) => return error(&n.span, format!("this function has to return {}", return_type)),
+        (None, None) => {}
+    }
+    asm.jump(return_label);
+    Ok(())
+}
+
+fn check_arity(n: &Node, func_name: Symbol, arity: usize) -> GenResult<()> {
+    if n.children.len() != arity {
+        let plural = |count: usize| if count == 1 { "" } else { "s" };
+        return error(&n.span, format!("{} takes {} argument{} but {} {} given", func_name, arity, plural(arity),
+                                      n.children.len(), if n.children.len() == 1 { "was" } else { "were" }));
+    }
+    Ok(())
+}
+
+fn builtin_call(n: &Node, func_name: Symbol, asm: &mut Assembler) -> GenResult<()> {
+    check_arity(n, func_name, 1)?;
+    let func_param: &Node = &n.children[0];
+
+    // An array's length is part of its type
+    if func_name.as_str() == "len" {
+        match expression(func_param, asm)? {
+            VariableType::Array(length) => asm.load_int(length),
+            other => return error(&func_param.span, format!("len takes an array, not {}", other))
+        }
+        return Ok(());
+    }
+
+    // Literals are already laid out as text in .data, anything else is worked out at runtime
+    match func_param.kind {
+        ASTNodeKind::Integer | ASTNodeKind::String => {
+            asm.builtin_function(func_name.as_str(), func_param.val.unwrap());
+        },
+        _ => {
+            let value_type = expression(func_param, asm)?;
+            if !value_type.is_scalar() {
+                return error(&func_param.span, String::from("arrays and structs can't be printed"));
             }
+            asm.print_value(value_type);
         }
     }
+    Ok(())
+}
+
+// Integer literals hold their decimal value, chars are stored as their code point
+fn scalar_value(n: &Node) -> u64 {
+    let value: Symbol = n.val.unwrap();
+    let parsed_value = match n.kind {
+        ASTNodeKind::Char => value.as_str().chars().next().map(|c| c as u64),
+        ASTNodeKind::Bool => Some(if value.as_str() == "true" { 1 } else { 0 }),
+        _ => u64::from_str(value.as_str()).ok()
+    };
+
+    match parsed_value {
+        Some(v) => v,
+        None => panic!("{}: {} isn't a valid value", n.span, value)
+    }
 }
 
 fn is_function_builtin(function_name: &str) -> bool {
     match function_name {
-        "print" => true,
+        "print" | "len" => true,
         _ => false
     }
 }
 
-fn write_elf(output_file: &mut File, ast: Vec<Node>, const_data: &ConstData) {
+fn write_elf(output_file: &mut File, assembler: &Assembler, const_data: &ConstData) {
     let const_section_data = const_data.get_data();
 
     let mut elf_header = elfwriter::ElfHeader::new();
-    let elf_text_program_header = elfwriter::ElfProgramHeader::new();
+    let mut elf_text_program_header = elfwriter::ElfProgramHeader::new();
     let mut elf_data_program_header = elfwriter::ElfProgramHeader::new();
 
     // ELF header (64) + .text phead (56) + .data phead (56)
@@ -88,14 +1121,16 @@ fn write_elf(output_file: &mut File, ast: Vec<Node>, const_data: &ConstData) {
     let section_header_size: u16 = 64;
 
     let asm_offset = sh_data_offset + sh_data_length;
-    let assembler = build_asm(ast, &const_data);
     let asm_length = assembler.get_length();
     let asm_data = assembler.get_output();
 
     let section_header_offset: u64 = asm_offset + asm_length;
 
+    // .text is mapped from the start of the file, so it has to cover the headers and .data too
+    elf_text_program_header.set_size(section_header_offset.to_be());
+
     // Instructions start at end of .data
-    elf_header.set_entry(0xb000400000000000 + sh_data_length.to_be());
+    elf_header.set_entry((0x00000000004000b0 + sh_data_length).to_be());
     elf_header.set_shnum(section_header_count.to_be());
     elf_header.set_shentsize(section_header_size.to_be());
     elf_header.set_shoff(section_header_offset.to_be());
@@ -168,3 +1203,66 @@ fn write_elf(output_file: &mut File, ast: Vec<Node>, const_data: &ConstData) {
     sh_strtab.write(output_file);
     elf_string_table.write(output_file);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{declare_globals, build_asm};
+    use parser::Parser;
+    use scanner::{Scanner, TokenStream};
+
+    // Every diagnostic the generator gives for a program that parses cleanly
+    fn errors(source: &str) -> Vec<String> {
+        let mut parser = Parser::new(TokenStream::new(Scanner::new("test.txt", source)));
+        let ast = parser.start().unwrap();
+        let mut data = parser.get_const_data().clone();
+        let mut errors = Vec::new();
+        let (globals, structs) = declare_globals(&ast, &mut data, &mut errors);
+        let asm = build_asm(ast, &data, globals, structs);
+        errors.extend(asm.errors.iter().cloned());
+        errors.iter().map(|e| e.message.clone()).collect()
+    }
+
+    #[test]
+    fn errors_inside_an_else_if_chain_are_reported() {
+        assert_eq!(errors("if 1 == 1 { print(1); } else if nope { print(2); }"),
+                   vec!["nope isn't declared"]);
+    }
+
+    #[test]
+    fn both_sides_of_a_logical_op_have_to_be_bools() {
+        assert_eq!(errors("print(true && 5);"), vec!["expected bool, found i64"]);
+        assert_eq!(errors("print(5 || true);"), vec!["expected bool, found i64"]);
+    }
+
+    #[test]
+    fn types_are_written_as_in_the_source() {
+        assert_eq!(errors("if 3 { print(1); }"), vec!["a condition has to be a bool, not i64"]);
+        assert_eq!(errors("let a = [1, 2]; let c: char = a;"), vec!["c is declared as char, but its value is [i64; 2]"]);
+        assert_eq!(errors("struct P { x: i64 } let p = P { x: 1 }; let b: bool = p;"),
+                   vec!["b is declared as bool, but its value is P"]);
+    }
+
+    // Frame slots are 32-bit displacements, so anything that can't be reached with one is refused
+    #[test]
+    fn arrays_and_frames_have_to_fit_a_displacement() {
+        let too_long = vec!["an array can't be longer than 268435455 elements"];
+        assert_eq!(errors("let a = [0; 9223372036854775807];"), too_long);
+        assert_eq!(errors("let a = [0; 600000000];"), too_long);
+        assert_eq!(errors("let a: [i64; 300000000] = [0; 3];"), too_long);
+
+        assert_eq!(errors("struct S { a: [i64; 200000000], b: [i64; 200000000] }"),
+                   vec!["struct S can't be bigger than 2147483647 bytes"]);
+
+        let too_big = vec!["variables can't take up more than 2147483647 bytes of stack"];
+        assert_eq!(errors("let a = [0; 100000000]; let b = [0; 100000000]; let c = 1;"), too_big);
+        assert_eq!(errors("fn f() { let a = [0; 100000000]; let b = [0; 100000000]; }"), too_big);
+        assert!(errors("let a = [0; 100000000];").is_empty());
+    }
+
+    // The bounds are the outer n, the loop variable only exists inside the loop
+    #[test]
+    fn range_bounds_are_evaluated_before_the_loop_variable_exists() {
+        assert_eq!(errors("let n = true; for n in 0..n { print(n); }"), vec!["a range has to be made of integers, not bool"]);
+        assert_eq!(errors("for n in 0..n { print(n); }"), vec!["n isn't declared"]);
+    }
+}
@@ -0,0 +1,67 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+
+// Handle to an interned string. Comparing and hashing a Symbol is comparing an integer; the
+// text is only looked up when something needs to print or emit it.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+impl Symbol {
+    pub fn intern(string: &str) -> Symbol {
+        INTERNER.with(|interner| interner.borrow_mut().intern(string))
+    }
+
+    // The empty string, always interned first
+    pub fn empty() -> Symbol {
+        Symbol(0)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        INTERNER.with(|interner| interner.borrow().lookup(*self))
+    }
+}
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+struct Interner {
+    symbols: HashMap<&'static str, Symbol>,
+    strings: Vec<&'static str>
+}
+impl Interner {
+    fn new() -> Interner {
+        let mut interner = Interner {symbols: HashMap::new(), strings: Vec::new()};
+        interner.intern("");
+
+        interner
+    }
+
+    fn intern(&mut self, string: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(string) {
+            return symbol;
+        }
+
+        // Interned strings live until the compiler exits, so they're leaked rather than owned here.
+        // That's what lets as_str() hand out a plain &'static str.
+        let stored: &'static str = Box::leak(string.to_string().into_boxed_str());
+        let symbol = Symbol(self.strings.len() as u32);
+        self.strings.push(stored);
+        self.symbols.insert(stored, symbol);
+
+        symbol
+    }
+
+    fn lookup(&self, symbol: Symbol) -> &'static str {
+        self.strings[symbol.0 as usize]
+    }
+}
+
+// One interner for the whole compile
+thread_local!(static INTERNER: RefCell<Interner> = RefCell::new(Interner::new()));
@@ -1,6 +1,209 @@
+// The scanner is exposed as a library so it can be benchmarked on its own
+pub mod interner;
+pub mod span;
+pub mod scanner;
+
 #[cfg(test)]
 mod tests {
+    use scanner::{Scanner, TokenStream, TokenType, LexErrorKind, IntegerSuffix};
+
+    // Type and value of every token, or the first lexical error
+    fn lex(source: &str) -> Result<Vec<(TokenType, &'static str)>, LexErrorKind> {
+        Scanner::new("test.txt", source)
+            .map(|tok| tok.map(|t| (t.get_type(), t.get_val().as_str())).map_err(|e| e.kind))
+            .collect()
+    }
+
+    // What lex() gives back for a source that scans cleanly
+    fn tokens(expected: &[(TokenType, &'static str)]) -> Result<Vec<(TokenType, &'static str)>, LexErrorKind> {
+        Ok(expected.to_vec())
+    }
+
     #[test]
     fn it_works() {
     }
+
+    // Line, column, start and end of every token
+    fn spans(source: &str) -> Vec<(usize, usize, usize, usize)> {
+        Scanner::new("test.txt", source)
+            .map(|tok| { let s = tok.unwrap().get_span().clone(); (s.line, s.column, s.start, s.end) })
+            .collect()
+    }
+
+    // Lines and columns count characters from 1, start and end are byte offsets
+    #[test]
+    fn token_spans() {
+        assert_eq!(spans("let x = 10;"),
+                   vec![(1, 1, 0, 3), (1, 5, 4, 5), (1, 7, 6, 7), (1, 9, 8, 10), (1, 11, 10, 11)]);
+        assert_eq!(spans("a\n\n  b"), vec![(1, 1, 0, 1), (3, 3, 5, 6)]);
+        assert_eq!(spans("\"é\" x"), vec![(1, 1, 0, 4), (1, 5, 5, 6)]);
+
+        let token = Scanner::new("test.txt", "\n  x").next().unwrap().unwrap();
+        assert_eq!(token.get_span().to_string(), "test.txt:2:3");
+    }
+
+    // A character that can't start a token is an error of its own, and scanning carries on after it
+    #[test]
+    fn invalid_characters() {
+        let tokens: Vec<_> = Scanner::new("test.txt", "a $ b").map(|tok| tok.map_err(|e| e.kind)).collect();
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[1], Err(LexErrorKind::InvalidCharacter('$')));
+        assert_eq!(tokens[2].as_ref().unwrap().get_val().as_str(), "b");
+        assert_eq!(lex("#"), Err(LexErrorKind::InvalidCharacter('#')));
+    }
+
+    // Tabs and carriage returns are whitespace like any other, and only '\n' starts a line
+    #[test]
+    fn tabs_and_carriage_returns() {
+        assert_eq!(lex("a\tb\r\nc"), tokens(&[(TokenType::Identifier, "a"), (TokenType::Identifier, "b"),
+                                             (TokenType::Identifier, "c")]));
+        assert_eq!(spans("a\tb\r\nc"), vec![(1, 1, 0, 1), (1, 3, 2, 3), (2, 1, 5, 6)]);
+    }
+
+    // Keywords only match whole words, anything longer is an identifier
+    #[test]
+    fn keywords_and_identifiers() {
+        assert_eq!(lex("let mut fn if else while for in"),
+                   tokens(&[(TokenType::Let, ""), (TokenType::Mut, ""), (TokenType::Fn, ""), (TokenType::If, ""),
+                            (TokenType::Else, ""), (TokenType::While, ""), (TokenType::For, ""), (TokenType::In, "")]));
+        assert_eq!(lex("letter mutable fn_ while1 _in True"),
+                   tokens(&[(TokenType::Identifier, "letter"), (TokenType::Identifier, "mutable"),
+                            (TokenType::Identifier, "fn_"), (TokenType::Identifier, "while1"),
+                            (TokenType::Identifier, "_in"), (TokenType::Identifier, "True")]));
+        assert_eq!(lex("x_1 _ été"), tokens(&[(TokenType::Identifier, "x_1"), (TokenType::Identifier, "_"),
+                                              (TokenType::Identifier, "été")]));
+    }
+
+    // The longest operator wins, so == is never = followed by =
+    #[test]
+    fn operators_take_the_longest_match() {
+        assert_eq!(lex("a==b!=c<=d>=e&&f||g..h->i"),
+                   tokens(&[(TokenType::Identifier, "a"), (TokenType::Equality, ""), (TokenType::Identifier, "b"),
+                            (TokenType::NotEqual, ""), (TokenType::Identifier, "c"), (TokenType::LessEqual, ""),
+                            (TokenType::Identifier, "d"), (TokenType::GreaterEqual, ""), (TokenType::Identifier, "e"),
+                            (TokenType::And, ""), (TokenType::Identifier, "f"), (TokenType::Or, ""),
+                            (TokenType::Identifier, "g"), (TokenType::DotDot, ""), (TokenType::Identifier, "h"),
+                            (TokenType::Arrow, ""), (TokenType::Identifier, "i")]));
+        assert_eq!(lex("= ! < > & | . -"),
+                   tokens(&[(TokenType::EqualSign, ""), (TokenType::Not, ""), (TokenType::Less, ""),
+                            (TokenType::Greater, ""), (TokenType::BitAnd, ""), (TokenType::BitOr, ""),
+                            (TokenType::Dot, ""), (TokenType::Subtract, "")]));
+        assert_eq!(lex("===<<>>...+=-=*=/="),
+                   tokens(&[(TokenType::Equality, ""), (TokenType::EqualSign, ""), (TokenType::ShiftLeft, ""),
+                            (TokenType::ShiftRight, ""), (TokenType::DotDot, ""), (TokenType::Dot, ""),
+                            (TokenType::AddAssign, ""), (TokenType::SubtractAssign, ""),
+                            (TokenType::MultiplyAssign, ""), (TokenType::DivideAssign, "")]));
+    }
+
+    // Peeking never consumes, doc comments are skipped and lexical errors are set aside
+    #[test]
+    fn token_stream_lookahead() {
+        let mut stream = TokenStream::new(Scanner::new("test.txt", "a /// doc\n b $ c"));
+        assert_eq!(stream.peek(2).map(|t| t.get_val().as_str()), Some("c"));
+        assert_eq!(stream.peek(0).map(|t| t.get_val().as_str()), Some("a"));
+        assert!(stream.peek(3).is_none());
+
+        assert_eq!(stream.next().map(|t| t.get_val().as_str()), Some("a"));
+        assert_eq!(stream.peek(0).map(|t| t.get_val().as_str()), Some("b"));
+        let rest: Vec<_> = stream.by_ref().map(|t| t.get_val().as_str()).collect();
+        assert_eq!(rest, vec!["b", "c"]);
+        assert!(stream.peek(0).is_none());
+        assert_eq!(stream.get_errors().len(), 1);
+    }
+
+    #[test]
+    fn string_escapes() {
+        assert_eq!(lex(r#""plain""#), tokens(&[(TokenType::StringLiteral, "plain")]));
+        assert_eq!(lex(r#""a\tb\r\n\0""#), tokens(&[(TokenType::StringLiteral, "a\tb\r\n\0")]));
+        assert_eq!(lex(r#""\\ \" \'""#), tokens(&[(TokenType::StringLiteral, "\\ \" '")]));
+        assert_eq!(lex(r#""\x41\x7f""#), tokens(&[(TokenType::StringLiteral, "A\x7f")]));
+        assert_eq!(lex(r#""\u{e9}\u{1F600}""#),
+                   tokens(&[(TokenType::StringLiteral, "\u{e9}\u{1F600}")]));
+    }
+
+    #[test]
+    fn invalid_string_escapes() {
+        assert_eq!(lex(r#""\q""#), Err(LexErrorKind::InvalidEscape("\\q".to_string())));
+        assert_eq!(lex(r#""\x80""#), Err(LexErrorKind::InvalidEscape("\\x80".to_string())));
+        assert_eq!(lex(r#""\x4""#), Err(LexErrorKind::InvalidEscape("\\x4".to_string())));
+        assert_eq!(lex(r#""\u{}""#), Err(LexErrorKind::InvalidEscape("\\u{}".to_string())));
+        assert_eq!(lex(r#""\u{110000}""#), Err(LexErrorKind::InvalidEscape("\\u{110000}".to_string())));
+        assert_eq!(lex(r#""\u41""#), Err(LexErrorKind::InvalidEscape("\\u".to_string())));
+        assert_eq!(lex(r#""open"#), Err(LexErrorKind::Unterminated("string literal")));
+    }
+
+    // Scanning carries on past a bad escape to the closing quote
+    #[test]
+    fn scanning_continues_after_bad_escape() {
+        let tokens: Vec<_> = Scanner::new("test.txt", r#""\q" x"#).collect();
+        assert_eq!(tokens.len(), 2);
+        assert!(tokens[0].is_err());
+        assert_eq!(tokens[1].as_ref().unwrap().get_val().as_str(), "x");
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(lex("a // b\nc"), tokens(&[(TokenType::Identifier, "a"), (TokenType::Identifier, "c")]));
+        assert_eq!(lex("a /* b /* c */ d */ e"),
+                   tokens(&[(TokenType::Identifier, "a"), (TokenType::Identifier, "e")]));
+        assert_eq!(lex("/**/x/***/"), tokens(&[(TokenType::Identifier, "x")]));
+        assert_eq!(lex("/// doc\n//// not doc"), tokens(&[(TokenType::DocComment, " doc")]));
+        assert_eq!(lex("/* /* */"), Err(LexErrorKind::Unterminated("block comment")));
+    }
+
+    // The token's value is always the plain decimal number
+    #[test]
+    fn integer_literals() {
+        assert_eq!(lex("42"), tokens(&[(TokenType::Integer, "42")]));
+        assert_eq!(lex("0x2A"), tokens(&[(TokenType::Integer, "42")]));
+        assert_eq!(lex("0o17"), tokens(&[(TokenType::Integer, "15")]));
+        assert_eq!(lex("0b1010_1010"), tokens(&[(TokenType::Integer, "170")]));
+        assert_eq!(lex("1_000_000"), tokens(&[(TokenType::Integer, "1000000")]));
+        assert_eq!(lex("9223372036854775807"),
+                   tokens(&[(TokenType::Integer, "9223372036854775807")]));
+        assert_eq!(lex("7i64"), tokens(&[(TokenType::Integer, "7")]));
+
+        let token = Scanner::new("test.txt", "0xffi64").next().unwrap().unwrap();
+        assert_eq!(token.get_suffix(), Some(IntegerSuffix::I64));
+    }
+
+    #[test]
+    fn invalid_integer_literals() {
+        assert_eq!(lex("0x"), Err(LexErrorKind::InvalidLiteral("0x".to_string())));
+        assert_eq!(lex("0b102"), Err(LexErrorKind::InvalidLiteral("0b102".to_string())));
+        assert_eq!(lex("12abc"), Err(LexErrorKind::InvalidLiteral("12abc".to_string())));
+        assert_eq!(lex("9223372036854775808"), Err(LexErrorKind::LiteralOutOfRange("9223372036854775808".to_string())));
+        assert_eq!(lex("0x8000_0000_0000_0000"), Err(LexErrorKind::LiteralOutOfRange("0x8000_0000_0000_0000".to_string())));
+    }
+
+    // Each suffix is checked against its own type, and none can go past what an i64 holds
+    #[test]
+    fn suffixed_integer_literals() {
+        assert_eq!(lex("42u8"), tokens(&[(TokenType::Integer, "42")]));
+        assert_eq!(lex("7i32"), tokens(&[(TokenType::Integer, "7")]));
+        assert_eq!(lex("0xffu8"), tokens(&[(TokenType::Integer, "255")]));
+        assert_eq!(lex("9223372036854775807u64"),
+                   tokens(&[(TokenType::Integer, "9223372036854775807")]));
+        assert_eq!(lex("300u8"), Err(LexErrorKind::LiteralOutOfRange("300u8".to_string())));
+        assert_eq!(lex("128i8"), Err(LexErrorKind::LiteralOutOfRange("128i8".to_string())));
+        assert_eq!(lex("4294967296u32"), Err(LexErrorKind::LiteralOutOfRange("4294967296u32".to_string())));
+        assert_eq!(lex("18446744073709551615u64"),
+                   Err(LexErrorKind::LiteralOutOfRange("18446744073709551615u64".to_string())));
+        assert_eq!(lex("1u7"), Err(LexErrorKind::InvalidLiteral("1u7".to_string())));
+    }
+
+    // A quote starts a char literal if another one closes it, otherwise it's a label
+    #[test]
+    fn labels_and_char_literals() {
+        assert_eq!(lex("'a'"), tokens(&[(TokenType::CharLiteral, "a")]));
+        assert_eq!(lex("'\\n'"), tokens(&[(TokenType::CharLiteral, "\n")]));
+        assert_eq!(lex("' '"), tokens(&[(TokenType::CharLiteral, " ")]));
+        assert_eq!(lex("'a"), tokens(&[(TokenType::Label, "a")]));
+        assert_eq!(lex("'outer: while"), tokens(&[(TokenType::Label, "outer"), (TokenType::Colon, ""),
+                                                  (TokenType::While, "")]));
+        assert_eq!(lex("break 'outer;"), tokens(&[(TokenType::Break, ""), (TokenType::Label, "outer"),
+                                                  (TokenType::Terminator, "")]));
+        assert_eq!(lex("'ab'"), Err(LexErrorKind::InvalidChar("ab".to_string())));
+        assert_eq!(lex("''"), Err(LexErrorKind::InvalidChar("".to_string())));
+    }
 }
@@ -1,7 +1,8 @@
-#![feature(box_syntax, box_patterns)]
-
 extern crate byteorder;
 
+mod interner;
+mod span;
+mod diagnostic;
 mod scanner;
 mod parser;
 mod tree;
@@ -14,34 +15,83 @@ mod asm_macro;
 mod asm_opcode;
 mod symboltable;
 
+use std::env;
 use std::error::Error;
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::Path;
+use std::process;
 use parser::Parser as Parser;
+use scanner::Scanner as Scanner;
+use scanner::TokenStream as TokenStream;
+
+const SOURCE_FILE: &str = "test.txt";
 
 fn main() {
-    let program = read();
-    let tokens = scanner::scan(program);
-    for t in &tokens {
-        println!("{:?}", t);
+    let program = read(SOURCE_FILE);
+    let tokens = TokenStream::new(Scanner::new(SOURCE_FILE, &program));
+
+    let error_limit = error_limit();
+    let mut p = Parser::new(tokens);
+    p.set_error_limit(error_limit);
+    let parsed = p.start();
+
+    // Syntax errors right after a lexical one are usually caused by it, so those come first
+    let lex_errors = p.get_lex_errors();
+    for e in lex_errors {
+        eprintln!("error: {}", e);
     }
 
-    let mut p = Parser::new(&tokens);
-    let ast = p.start();
+    let ast = match parsed {
+        Ok(_) if !lex_errors.is_empty() => process::exit(1),
+        Ok(ast) => ast,
+        Err(diagnostics) => {
+            let count = diagnostics.len();
+            for d in diagnostics {
+                eprintln!("error: {}", d);
+            }
+            if p.stopped_early() {
+                eprintln!("error: stopped after {} errors", count);
+            }
+            process::exit(1);
+        }
+    };
+
     let const_data = p.get_const_data();
 
     println!("{:#?}", ast);
     if !ast.is_empty() {
-        generator::generate(ast, const_data, "out.bin");
+        if let Err(errors) = generator::generate(ast, const_data, "out.bin") {
+            for e in errors {
+                eprintln!("error: {}", e);
+            }
+            process::exit(1);
+        }
     } else {
         panic!("No valid AST generated");
     }
 }
 
-fn read() -> String {
+// --error-limit=N caps how many syntax errors are reported
+fn error_limit() -> usize {
+    for arg in env::args().skip(1) {
+        if let Some(limit) = arg.strip_prefix("--error-limit=") {
+            match limit.parse() {
+                Ok(limit) if limit > 0 => return limit,
+                _ => {
+                    eprintln!("error: {} needs a positive number", arg);
+                    process::exit(1);
+                }
+            }
+        }
+    }
+
+    parser::DEFAULT_ERROR_LIMIT
+}
+
+fn read(file: &str) -> String {
 
-    let path = Path::new("test.txt");
+    let path = Path::new(file);
     let display = path.display();
 
     let mut f = match File::open(&path) {
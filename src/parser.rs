@@ -1,201 +1,809 @@
 use scanner::TokenType as Tokens;
 use scanner::Token as Token;
+use scanner::TokenStream as TokenStream;
+use scanner::LexError as LexError;
 use tree::Node as Node;
 use tree::ASTNodeKind as ASTType;
+use tree::Operator as Operator;
+use tree::Program as Program;
+use diagnostic::Diagnostic as Diagnostic;
 use constdata::ConstData as ConstData;
+use span::Span as Span;
+use interner::Symbol as Symbol;
+
+type ParseResult = Result<Node, Diagnostic>;
+
+const EXPONENT_PRECEDENCE: u8 = 6;
+
+// Syntax errors reported before the parser gives up on the file
+pub const DEFAULT_ERROR_LIMIT: usize = 20;
+
+// Statements that end in a closing brace don't need a ';' after them
+fn needs_terminator(stmt: &Node) -> bool {
+    !matches!(stmt.kind, ASTType::Block | ASTType::If | ASTType::While | ASTType::For |
+                         ASTType::FunctionDef | ASTType::StructDef)
+}
+
+// Tokens that can only start a statement, so are a safe place to pick up again after an error
+fn starts_statement(token: Tokens) -> bool {
+    matches!(token, Tokens::Let | Tokens::Fn | Tokens::If | Tokens::While | Tokens::For | Tokens::Return |
+                    Tokens::Break | Tokens::Continue | Tokens::Const | Tokens::Static | Tokens::Struct)
+}
+
+// Operator, precedence (higher binds tighter) and whether it's right associative
+fn binary_operator(token: Tokens) -> Option<(Operator, u8, bool)> {
+    match token {
+        Tokens::Or           => Some((Operator::Or, 1, false)),
+        Tokens::And          => Some((Operator::And, 2, false)),
+        Tokens::Equality     => Some((Operator::Equal, 3, false)),
+        Tokens::NotEqual     => Some((Operator::NotEqual, 3, false)),
+        Tokens::Less         => Some((Operator::Less, 3, false)),
+        Tokens::LessEqual    => Some((Operator::LessEqual, 3, false)),
+        Tokens::Greater      => Some((Operator::Greater, 3, false)),
+        Tokens::GreaterEqual => Some((Operator::GreaterEqual, 3, false)),
+        Tokens::Add      => Some((Operator::Add, 4, false)),
+        Tokens::Subtract => Some((Operator::Subtract, 4, false)),
+        Tokens::Multiply => Some((Operator::Multiply, 5, false)),
+        Tokens::Divide   => Some((Operator::Divide, 5, false)),
+        Tokens::Modulus  => Some((Operator::Modulus, 5, false)),
+        Tokens::Exponent => Some((Operator::Exponent, EXPONENT_PRECEDENCE, true)),
+        _ => None
+    }
+}
 
 pub struct Parser<'a>
 {
-    tokens: &'a Vec<Token>,
+    tokens: TokenStream<'a>,
     index: usize,
-    const_data: ConstData
+    last_span: Span,
+    const_data: ConstData,
+    errors: Vec<Diagnostic>,
+    error_limit: usize,
+    // Whether the error limit stopped parsing before the end of the file, so there may be errors
+    // that weren't reported
+    stopped_early: bool,
+    // Off where a '{' has to start a block, as after an if condition, so `if x {` isn't a struct literal
+    struct_literals: bool
 }
 
 impl<'a> Parser<'a>
 {
-    pub fn new(toks: &Vec<Token>) -> Parser {
-        Parser { tokens: toks, index: 0, const_data: ConstData::new() }
+    pub fn new(tokens: TokenStream<'a>) -> Parser<'a> {
+        Parser {
+            tokens,
+            index: 0,
+            last_span: Span::default(),
+            const_data: ConstData::new(),
+            errors: Vec::new(),
+            error_limit: DEFAULT_ERROR_LIMIT,
+            stopped_early: false,
+            struct_literals: true
+        }
     }
 
-    fn consume(&mut self, token: Tokens) -> bool {
+    pub fn set_error_limit(&mut self, limit: usize) {
+        self.error_limit = limit;
+    }
 
-        if self.index >= self.tokens.len() {
-            return false;
+    pub fn stopped_early(&self) -> bool {
+        self.stopped_early
+    }
 
-        } else if self.tokens[self.index].is_type(token) {
+    // Take the next token, keeping count of how far we've got
+    fn advance(&mut self) -> Option<Token> {
+        let tok = self.tokens.next();
+        if let Some(ref t) = tok {
             self.index += 1;
+            self.last_span = t.get_span().clone();
+        }
 
-            return true;
+        tok
+    }
 
+    fn consume(&mut self, token: Tokens) -> bool {
+        self.consume_token(token).is_some()
+    }
+
+    fn consume_token(&mut self, token: Tokens) -> Option<Token> {
+
+        if self.peek() == Some(token) {
+            self.advance()
         } else {
-            return false;
+            None
         }
     }
 
-    fn consume_token(&mut self, token: Tokens) -> Option<Token> {
+    // Consume the given token or complain about whatever is there instead
+    fn expect(&mut self, token: Tokens) -> Result<Token, Diagnostic> {
+        match self.consume_token(token.clone()) {
+            Some(t) => Ok(t),
+            None => Err(self.unexpected(&token.to_string()))
+        }
+    }
+
+    // Error for the next token not being what we wanted
+    fn unexpected(&mut self, expected: &str) -> Diagnostic {
+        if let Some(t) = self.tokens.peek(0) {
+            return Diagnostic::new(expected, t.to_string(), t.get_span().clone());
+        }
 
-        if self.index >= self.tokens.len() {
-            return None;
+        // Point just past the last token when the file ends early
+        let mut span = self.last_span();
+        span.column += span.end - span.start;
+        span.start = span.end;
+        Diagnostic::new(expected, String::from("end of file"), span)
+    }
 
-        } else if self.tokens[self.index].is_type(token) {
-            self.index += 1;
+    fn peek(&mut self) -> Option<Tokens> {
+        self.tokens.peek(0).map(|t| t.get_type())
+    }
 
-            return Some(self.tokens[self.index - 1].clone());
+    // Span of the most recently consumed token
+    fn last_span(&self) -> Span {
+        self.last_span.clone()
+    }
 
+    fn peek_ahead(&mut self) -> Option<Tokens> {
+        self.tokens.peek(1).map(|t| t.get_type())
+    }
+
+    pub fn start(&mut self) -> Result<Program, Vec<Diagnostic>> {
+        let ast = self.statement_list(false);
+        if self.peek().is_some() {
+            self.stopped_early = true;
+        }
+
+        if self.errors.is_empty() {
+            Ok(ast)
         } else {
-            return None;
+            Err(self.errors.split_off(0))
         }
     }
 
-    fn peek(&self) -> Option<Tokens> {
+    // Statements up to the end of the file, or up to the closing brace of a block
+    fn statement_list(&mut self, in_block: bool) -> Vec<Node> {
+        let mut statements = vec!();
 
-        if (self.index) >= self.tokens.len() {
-            return None
-        } else {
-            return Some(self.tokens[self.index].get_type())
+        while self.peek().is_some() && self.errors.len() < self.error_limit {
+            if in_block && self.peek() == Some(Tokens::BraceClose) {
+                break;
+            }
+            let last_index = self.index;
+
+            match self.statement() {
+                Ok(stmt) => {
+                    if !needs_terminator(&stmt) {
+                        statements.push(stmt);
+                        continue;
+                    }
+                    match self.expect(Tokens::Terminator) {
+                        Ok(_) => statements.push(stmt),
+                        Err(e) => self.recover(e, last_index)
+                    }
+                },
+                Err(e) => self.recover(e, last_index)
+            }
+        }
+
+        statements
+    }
+
+    // Panic mode: note the error, then throw tokens away until the start of the next statement.
+    // Any block opened along the way is skipped whole, so its closing brace doesn't end ours.
+    // Once the limit is hit we're only unwinding, and the enclosing blocks missing their closing
+    // braces isn't worth reporting.
+    fn recover(&mut self, error: Diagnostic, last_index: usize) {
+        if self.errors.len() < self.error_limit {
+            self.errors.push(error);
+        }
+        let mut depth = 0;
+
+        loop {
+            match self.peek() {
+                None => break,
+                Some(Tokens::BraceOpen) => {
+                    depth += 1;
+                    self.advance();
+                },
+                Some(Tokens::BraceClose) => {
+                    if depth == 0 {
+                        break;
+                    }
+                    self.advance();
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                },
+                Some(Tokens::Terminator) if depth == 0 => {
+                    self.advance();
+                    break;
+                },
+                Some(t) => {
+                    if depth == 0 && starts_statement(t) {
+                        break;
+                    }
+                    self.advance();
+                }
+            }
+        }
+
+        // The statement died on its very first token, which is also where we stopped. Skip it
+        // or we'd be stuck reporting the same error forever.
+        if self.index == last_index {
+            self.advance();
         }
     }
 
-    fn peek_ahead(&self) -> Option<Tokens> {
 
-        if (self.index + 1) >= self.tokens.len() {
-            return None
+    // Statements are picked by their first token. Anything else is an expression evaluated for its
+    // effect, or the target of an assignment if an '=' follows it.
+    fn statement(&mut self) -> ParseResult {
+        if self.peek() == Some(Tokens::BraceOpen) {
+            return self.block();
+        } else if self.peek() == Some(Tokens::If) {
+            return self.if_statement();
+        } else if self.peek() == Some(Tokens::Label) {
+            return self.labelled_loop();
+        } else if self.peek() == Some(Tokens::While) || self.peek() == Some(Tokens::For) {
+            return self.loop_statement(None);
+        } else if self.peek() == Some(Tokens::Break) || self.peek() == Some(Tokens::Continue) {
+            return self.loop_jump();
+        } else if self.peek() == Some(Tokens::Fn) {
+            return self.function_definition();
+        } else if self.peek() == Some(Tokens::Struct) {
+            return self.struct_definition();
+        } else if self.peek() == Some(Tokens::Return) {
+            return self.return_statement();
+        } else if self.peek() == Some(Tokens::Let) {
+            return self.let_statement();
+        } else if self.peek() == Some(Tokens::Const) || self.peek() == Some(Tokens::Static) {
+            return self.global_declaration();
+        }
+
+        let expr = self.expression()?;
+        if self.consume(Tokens::EqualSign) {
+            self.assignment(expr)
         } else {
-            return Some(self.tokens[self.index + 1].get_type())
+            Ok(expr)
         }
     }
 
-    pub fn start(&mut self) -> Vec<Node> {
-        let mut ast = vec!();
+    // Braces around any number of statements, which get a scope of their own
+    fn block(&mut self) -> ParseResult {
+        let open = self.expect(Tokens::BraceOpen)?;
+        let statements = self.statement_list(true);
+        self.expect(Tokens::BraceClose)?;
 
-        let mut last_index = self.index;
+        let mut node = self.make_node(ASTType::Block, None, open.get_span().to(&self.last_span()));
+        node.children = statements;
+        Ok(node)
+    }
 
-        loop {
-            last_index = self.index;
-            let ass = self.assignment();
-            if ass.is_some() && self.terminator() {
-                ast.push(ass.unwrap());
-            }
+    // if cond { } with an optional else, which is either a block or the next if in an else if chain
+    fn if_statement(&mut self) -> ParseResult {
+        let start = self.expect(Tokens::If)?;
+        let condition = self.block_condition()?;
+        let then_block = self.block()?;
+
+        let mut node = self.make_node(ASTType::If, None, start.get_span().clone());
+        node.append_l(condition.get_kind(), condition);
+        node.children.push(then_block);
 
+        if self.consume(Tokens::Else) {
+            let else_branch = if self.peek() == Some(Tokens::If) { self.if_statement()? } else { self.block()? };
+            node.children.push(else_branch);
+        }
+
+        node.span = node.span.to(&self.last_span());
+        Ok(node)
+    }
+
+    // 'label: followed by the loop it names
+    fn labelled_loop(&mut self) -> ParseResult {
+        let label = self.expect(Tokens::Label)?;
+        self.expect(Tokens::Colon)?;
+
+        let mut node = self.loop_statement(Some(label.get_val()))?;
+        node.span = label.get_span().to(&node.span);
+        Ok(node)
+    }
+
+    fn loop_statement(&mut self, label: Option<Symbol>) -> ParseResult {
+        match self.peek() {
+            Some(Tokens::While) => self.while_loop(label),
+            Some(Tokens::For)   => self.for_loop(label),
+            _ => Err(self.unexpected("`while` or `for`"))
+        }
+    }
+
+    fn while_loop(&mut self, label: Option<Symbol>) -> ParseResult {
+        let start = self.expect(Tokens::While)?;
+        let condition = self.block_condition()?;
+        let body = self.block()?;
+
+        let mut node = self.make_node(ASTType::While, label, start.get_span().to(&self.last_span()));
+        node.append_l(condition.get_kind(), condition);
+        node.children.push(body);
+        Ok(node)
+    }
+
+    // for i in start..end { }, counting up from start and stopping before end
+    fn for_loop(&mut self, label: Option<Symbol>) -> ParseResult {
+        let start = self.expect(Tokens::For)?;
+        let variable = self.variable()?;
+        self.expect(Tokens::In)?;
+        let range_start = self.expression()?;
+        self.expect(Tokens::DotDot)?;
+        let range_end = self.block_condition()?;
+        let body = self.block()?;
+
+        let mut node = self.make_node(ASTType::For, label, start.get_span().to(&self.last_span()));
+        node.append_l(ASTType::Variable, variable);
+        node.children.push(range_start);
+        node.children.push(range_end);
+        node.children.push(body);
+        Ok(node)
+    }
+
+    // break or continue, optionally naming the loop they apply to
+    fn loop_jump(&mut self) -> ParseResult {
+        let kind = if self.consume(Tokens::Break) { ASTType::Break } else { self.expect(Tokens::Continue)?; ASTType::Continue };
+        let start = self.last_span();
+
+        let label = self.consume_token(Tokens::Label).map(|t| t.get_val());
+        Ok(self.make_node(kind, label, start.to(&self.last_span())))
+    }
+
+    // An expression straight before a block, where a struct literal would swallow the block
+    fn block_condition(&mut self) -> ParseResult {
+        let allowed = self.struct_literals;
+        self.struct_literals = false;
+        let condition = self.expression();
+        self.struct_literals = allowed;
+
+        condition
+    }
+
+    // fn name(a, b: type) [-> type] { }
+    fn function_definition(&mut self) -> ParseResult {
+        let start = self.expect(Tokens::Fn)?;
+        let name = self.expect(Tokens::Identifier)?;
+        self.expect(Tokens::ParenOpen)?;
+
+        let mut params = vec!();
+        if !self.consume(Tokens::ParenClose) {
+            loop {
+                let mut param = self.variable()?;
+                if self.consume(Tokens::Colon) {
+                    param.children.push(self.type_name()?);
+                }
+                params.push(param);
 
-            if let Some(current_token) = self.peek() {
-                match current_token {
-                    Tokens::FunctionCall => {
-                        let fun = self.funcall();
-                        if fun.is_some() && self.terminator() {
-                            ast.push(fun.unwrap());
-                        }
-                    },
-                    _ => {}
+                if self.consume(Tokens::ParenClose) {
+                    break;
+                }
+                if !self.consume(Tokens::Comma) {
+                    return Err(self.unexpected("`,` or `)`"));
                 }
             }
+        }
+
+        let return_type = if self.consume(Tokens::Arrow) { Some(self.type_name()?) } else { None };
+        let body = self.block()?;
+
+        let mut node = self.make_node(ASTType::FunctionDef, Some(name.get_val()), start.get_span().to(&self.last_span()));
+        node.append_l(ASTType::Block, body);
+        if let Some(return_type) = return_type {
+            node.append_r(ASTType::Type, return_type);
+        }
+        node.children = params;
+        Ok(node)
+    }
 
-            // If we haven't moved, we've either finished iterating or something went wrong.
-            if self.index == last_index {
+    // struct Name { field: type, ... }, with an optional trailing comma
+    fn struct_definition(&mut self) -> ParseResult {
+        let start = self.expect(Tokens::Struct)?;
+        let name = self.expect(Tokens::Identifier)?;
+        self.expect(Tokens::BraceOpen)?;
+
+        let mut fields = vec!();
+        while !self.consume(Tokens::BraceClose) {
+            let mut field = self.variable()?;
+            self.expect(Tokens::Colon)?;
+            field.children.push(self.type_name()?);
+            fields.push(field);
+
+            if !self.consume(Tokens::Comma) {
+                self.expect(Tokens::BraceClose)?;
                 break;
             }
         }
 
-        return ast;
+        let mut node = self.make_node(ASTType::StructDef, Some(name.get_val()), start.get_span().to(&self.last_span()));
+        node.children = fields;
+        Ok(node)
     }
 
+    // return, with or without a value
+    fn return_statement(&mut self) -> ParseResult {
+        let start = self.expect(Tokens::Return)?;
+        let mut node = self.make_node(ASTType::Return, None, start.get_span().clone());
 
-    fn variable(&mut self) -> Option<Node> {
+        if self.peek() != Some(Tokens::Terminator) {
+            let value = self.expression()?;
+            node.span = node.span.to(value.get_span());
+            node.append_l(value.get_kind(), value);
+        }
+        Ok(node)
+    }
 
-        if let Some(t) = self.consume_token(Tokens::Variable) {
-            return Some(self.make_node(ASTType::Variable, Some(t.get_val())));
-        } else {
-            return None;
+    fn expression(&mut self) -> ParseResult {
+        self.binary_expression(0)
+    }
+
+    // Precedence climbing: keep folding operators into the left hand side for as long as they
+    // bind at least as tightly as min_precedence
+    fn binary_expression(&mut self, min_precedence: u8) -> ParseResult {
+        let mut left = self.unary()?;
+
+        while let Some((op, precedence, right_assoc)) = self.peek().and_then(binary_operator) {
+            if precedence < min_precedence {
+                break;
+            }
+            self.advance();
+
+            // Right associative operators let an equal precedence operator bind to their right
+            let next_precedence = if right_assoc { precedence } else { precedence + 1 };
+            let right = self.binary_expression(next_precedence)?;
+
+            let mut node = self.make_node(ASTType::BinaryOp(op), None, left.get_span().to(right.get_span()));
+            node.append_l(left.get_kind(), left);
+            node.append_r(right.get_kind(), right);
+            left = node;
         }
+
+        Ok(left)
     }
 
-    fn equals(&mut self) -> bool {
-        if self.consume(Tokens::EqualSign) {
-            return true;
+    // Unary minus binds looser than ^, so -2^2 is -(2^2). ! binds tighter than anything.
+    fn unary(&mut self) -> ParseResult {
+        if self.consume(Tokens::Not) {
+            let start = self.last_span();
+            let operand = self.unary()?;
+
+            let mut node = self.make_node(ASTType::UnaryOp(Operator::Not), None, start.to(operand.get_span()));
+            node.append_l(operand.get_kind(), operand);
+            Ok(node)
+        } else if self.consume(Tokens::Subtract) {
+            let start = self.last_span();
+            let operand = self.binary_expression(EXPONENT_PRECEDENCE)?;
+
+            let mut node = self.make_node(ASTType::UnaryOp(Operator::Negate), None, start.to(operand.get_span()));
+            node.append_l(operand.get_kind(), operand);
+            Ok(node)
         } else {
-            return false;
+            self.postfix()
         }
     }
 
-    fn integer(&mut self) -> Option<Node> {
-        if let Some(t) = self.consume_token(Tokens::Integer) {
-            self.const_data.insert(&t.get_val());
-            return Some(self.make_node(ASTType::Integer, Some(t.get_val())));
-        } else {
-            return None;
+    fn variable(&mut self) -> ParseResult {
+        let t = self.expect(Tokens::Identifier)?;
+        Ok(self.make_node(ASTType::Variable, Some(t.get_val()), t.get_span().clone()))
+    }
+
+    fn integer(&mut self) -> ParseResult {
+        let t = self.expect(Tokens::Integer)?;
+        self.const_data.insert(t.get_val());
+        Ok(self.make_node(ASTType::Integer, Some(t.get_val()), t.get_span().clone()))
+    }
+
+    fn string(&mut self) -> ParseResult {
+        let t = self.expect(Tokens::StringLiteral)?;
+        self.const_data.insert(t.get_val());
+        Ok(self.make_node(ASTType::String, Some(t.get_val()), t.get_span().clone()))
+    }
+
+    fn character(&mut self) -> ParseResult {
+        let t = self.expect(Tokens::CharLiteral)?;
+        Ok(self.make_node(ASTType::Char, Some(t.get_val()), t.get_span().clone()))
+    }
+
+    fn boolean(&mut self) -> ParseResult {
+        let value = if self.consume(Tokens::True) { "true" } else { self.expect(Tokens::False)?; "false" };
+        let span = self.last_span();
+        Ok(self.make_node(ASTType::Bool, Some(Symbol::intern(value)), span))
+    }
+
+    fn literal(&mut self) -> ParseResult {
+        match self.peek() {
+            Some(Tokens::Integer)       => self.integer(),
+            Some(Tokens::StringLiteral) => self.string(),
+            Some(Tokens::CharLiteral)   => self.character(),
+            Some(Tokens::True) | Some(Tokens::False) => self.boolean(),
+            _ => Err(self.unexpected("an expression"))
         }
     }
 
-    fn assignment(&mut self) -> Option<Node> {
+    // let [mut] name [: type] = value
+    fn let_statement(&mut self) -> ParseResult {
+        let start = self.expect(Tokens::Let)?;
+        let mutable = self.consume(Tokens::Mut);
+        let v = self.variable()?;
+
+        let annotation = if self.consume(Tokens::Colon) { Some(self.type_name()?) } else { None };
 
-        let v = self.variable();
-        if !v.is_some() {
-            return None
+        self.expect(Tokens::EqualSign)?;
+        let r = self.expression()?;
+
+        let mut x = self.make_node(ASTType::Let(mutable), None, start.get_span().to(r.get_span()));
+        x.append_l(ASTType::Variable, v);
+        x.append_r(r.get_kind(), r);
+        x.children.extend(annotation);
+        Ok(x)
+    }
+
+    // const NAME: type = value or static [mut] NAME: type = value, where the type is required
+    fn global_declaration(&mut self) -> ParseResult {
+        let mut kind = if self.consume(Tokens::Const) {
+            ASTType::Const
+        } else {
+            self.expect(Tokens::Static)?;
+            ASTType::Static(false)
+        };
+        let start = self.last_span();
+        if kind == ASTType::Static(false) && self.consume(Tokens::Mut) {
+            kind = ASTType::Static(true);
         }
+        let v = self.variable()?;
 
-        let mut x = self.make_node(ASTType::Assignment, None);
+        self.expect(Tokens::Colon)?;
+        let annotation = self.type_name()?;
 
-        x.append_l(ASTType::Variable, v.unwrap());
+        self.expect(Tokens::EqualSign)?;
+        let r = self.expression()?;
 
-        if !self.equals() {
-            return None;
+        let mut x = self.make_node(kind, None, start.to(r.get_span()));
+        x.append_l(ASTType::Variable, v);
+        x.append_r(r.get_kind(), r);
+        x.children.push(annotation);
+        Ok(x)
+    }
+
+    // A type's name, or [element; length] for an array, which keeps its length expression as a child
+    fn type_name(&mut self) -> ParseResult {
+        if self.consume(Tokens::BracketOpen) {
+            let start = self.last_span();
+            let mut array_type = self.type_name()?;
+            self.expect(Tokens::Terminator)?;
+            array_type.children.push(self.expression()?);
+            self.expect(Tokens::BracketClose)?;
+
+            array_type.span = start.to(&self.last_span());
+            return Ok(array_type);
         }
 
-        let int = self.integer();
-        match int {
-            Some(r) => x.append_r(ASTType::Integer, r),
-            None    => return None
+        match self.consume_token(Tokens::Identifier) {
+            Some(t) => Ok(self.make_node(ASTType::Type, Some(t.get_val()), t.get_span().clone())),
+            None => Err(self.unexpected("a type"))
         }
+    }
+
+    // Called with the '=' consumed. Whether the target can be assigned to is up to the generator.
+    fn assignment(&mut self, target: Node) -> ParseResult {
+        let mut x = self.make_node(ASTType::Assignment, None, target.get_span().clone());
+        x.append_l(target.get_kind(), target);
+
+        let r = self.expression()?;
+        x.span = x.span.to(r.get_span());
+        x.append_r(r.get_kind(), r);
 
         // Parse OK, return expr tree
-        return Some(x);
+        Ok(x)
     }
 
-    fn funcall(&mut self) -> Option<Node> {
-        if let Some(t) = self.consume_token(Tokens::FunctionCall) {
-            let funcall = self.make_node(ASTType::FunctionCall, Some(t.get_val()));
+    fn primary(&mut self) -> ParseResult {
+        match self.peek() {
+            Some(Tokens::Identifier) if self.struct_literals && self.peek_ahead() == Some(Tokens::BraceOpen) =>
+                self.struct_literal(),
+            Some(Tokens::Identifier) => self.variable(),
+            Some(Tokens::ParenOpen)  => self.group(),
+            Some(Tokens::BracketOpen) => self.array_literal(),
+            _ => self.literal()
+        }
+    }
 
-            if self.consume(Tokens::ParenOpen) {
-                if let Some(function_with_params) = self.append_param_list(funcall) {
-                    return Some(function_with_params);
+    // [a, b, c] or [value; count]
+    fn array_literal(&mut self) -> ParseResult {
+        let start = self.expect(Tokens::BracketOpen)?;
+        let mut elements = vec!();
+
+        if !self.consume(Tokens::BracketClose) {
+            let first = self.expression()?;
+
+            if self.consume(Tokens::Terminator) {
+                let count = self.expression()?;
+                self.expect(Tokens::BracketClose)?;
+
+                let mut node = self.make_node(ASTType::ArrayRepeat, None, start.get_span().to(&self.last_span()));
+                node.append_l(first.get_kind(), first);
+                node.append_r(count.get_kind(), count);
+                return Ok(node);
+            }
+
+            elements.push(first);
+            while !self.consume(Tokens::BracketClose) {
+                if !self.consume(Tokens::Comma) {
+                    return Err(self.unexpected("`,` or `]`"));
                 }
+                if self.consume(Tokens::BracketClose) {
+                    break;
+                }
+                elements.push(self.expression()?);
             }
         }
 
-        return None;
+        let mut node = self.make_node(ASTType::ArrayLiteral, None, start.get_span().to(&self.last_span()));
+        node.children = elements;
+        Ok(node)
     }
 
-    // Recurse over param list, appending function params to right subtree
-    fn append_param_list(&mut self, mut node_list: Node) -> Option<Node> {
-        if self.consume(Tokens::ParenClose) {
-            return Some(node_list);
+    // Name { field: value, ... }, where a field on its own is short for field: field
+    fn struct_literal(&mut self) -> ParseResult {
+        let name = self.expect(Tokens::Identifier)?;
+        self.expect(Tokens::BraceOpen)?;
+
+        let mut fields = vec!();
+        while !self.consume(Tokens::BraceClose) {
+            let field_name = self.expect(Tokens::Identifier)?;
+            let value = if self.consume(Tokens::Colon) {
+                self.expression()?
+            } else {
+                self.make_node(ASTType::Variable, Some(field_name.get_val()), field_name.get_span().clone())
+            };
+
+            let mut field = self.make_node(ASTType::Field, Some(field_name.get_val()), field_name.get_span().to(value.get_span()));
+            field.append_l(value.get_kind(), value);
+            fields.push(field);
+
+            if !self.consume(Tokens::Comma) {
+                self.expect(Tokens::BraceClose)?;
+                break;
+            }
+        }
 
-        } else if let Some(param) = self.consume_token(Tokens::Integer) {
-            node_list.append_r(ASTType::Integer, self.make_node(ASTType::Integer, Some(param.get_val())));
-            self.const_data.insert(&param.get_val());
+        let mut node = self.make_node(ASTType::StructLiteral, Some(name.get_val()), name.get_span().to(&self.last_span()));
+        node.children = fields;
+        Ok(node)
+    }
+
+    // Parenthesised expression, which just becomes the inner expression. Struct literals are
+    // fine inside the parens even in an if condition.
+    fn group(&mut self) -> ParseResult {
+        self.expect(Tokens::ParenOpen)?;
+        let allowed = self.struct_literals;
+        self.struct_literals = true;
+        let inner = self.expression();
+        self.struct_literals = allowed;
+        let inner = inner?;
+        self.expect(Tokens::ParenClose)?;
+
+        Ok(inner)
+    }
 
-            if let Some(right_subtree) = node_list.get_right() {
-                self.append_param_list(right_subtree);
-                return Some(node_list);
+    // A primary followed by any number of calls, indexes and field accesses. The callee goes on the
+    // left, arguments in children; an indexed array goes on the left and the index on the right;
+    // a struct goes on the left of the field read from it.
+    fn postfix(&mut self) -> ParseResult {
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.consume(Tokens::ParenOpen) {
+                let mut funcall = self.make_node(ASTType::FunctionCall, None, expr.get_span().clone());
+                funcall.append_l(expr.get_kind(), expr);
+                funcall.children = self.argument_list()?;
+                funcall.span = funcall.span.to(&self.last_span());
+                expr = funcall;
+            } else if self.consume(Tokens::BracketOpen) {
+                let index = self.expression()?;
+                self.expect(Tokens::BracketClose)?;
+
+                let mut node = self.make_node(ASTType::Index, None, expr.get_span().to(&self.last_span()));
+                node.append_l(expr.get_kind(), expr);
+                node.append_r(index.get_kind(), index);
+                expr = node;
+            } else if self.consume(Tokens::Dot) {
+                let field = self.expect(Tokens::Identifier)?;
+
+                let mut node = self.make_node(ASTType::Field, Some(field.get_val()), expr.get_span().to(field.get_span()));
+                node.append_l(expr.get_kind(), expr);
+                expr = node;
+            } else {
+                break;
             }
         }
 
-        panic!("Something unexpected is in the function param list");
+        Ok(expr)
     }
 
-    fn terminator(&mut self) -> bool {
-        if self.consume(Tokens::Terminator) {
-            return true;
-        } else {
-            return false;
+    // Comma separated expressions up to the closing paren, which may follow a trailing comma
+    fn argument_list(&mut self) -> Result<Vec<Node>, Diagnostic> {
+        let mut args = vec!();
+
+        while !self.consume(Tokens::ParenClose) {
+            args.push(self.expression()?);
+
+            if !self.consume(Tokens::Comma) {
+                if self.consume(Tokens::ParenClose) {
+                    break;
+                }
+                return Err(self.unexpected("`,` or `)`"));
+            }
         }
+
+        Ok(args)
     }
 
-    fn make_node<'b>(&'b self, ast_type: ASTType, node_val: Option<String>) -> Node {
-        return Node {kind: ast_type, val: node_val, left: None, right: None};
+    fn make_node(&self, ast_type: ASTType, node_val: Option<Symbol>, span: Span) -> Node {
+        Node {kind: ast_type, val: node_val, span, left: None, right: None, children: Vec::new()}
     }
 
     pub fn get_const_data(&self) -> &ConstData {
         &self.const_data
     }
+
+    // Lexical errors the scanner ran into along the way
+    pub fn get_lex_errors(&self) -> &Vec<LexError> {
+        self.tokens.get_errors()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Parser;
+    use scanner::{Scanner, TokenStream};
+    use tree::{Node, ASTNodeKind};
+
+    // An expression statement written out with its grouping made explicit
+    fn grouping(source: &str) -> String {
+        let mut parser = Parser::new(TokenStream::new(Scanner::new("test.txt", source)));
+        let program = parser.start().unwrap();
+        sexpr(&program[0])
+    }
+
+    fn sexpr(n: &Node) -> String {
+        match n.kind {
+            ASTNodeKind::BinaryOp(op) =>
+                format!("({:?} {} {})", op, sexpr(n.left.as_ref().unwrap()), sexpr(n.right.as_ref().unwrap())),
+            ASTNodeKind::UnaryOp(op) => format!("({:?} {})", op, sexpr(n.left.as_ref().unwrap())),
+            _ => n.val.unwrap().to_string()
+        }
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        assert_eq!(grouping("1 - 2 - 3;"), "(Subtract (Subtract 1 2) 3)");
+        assert_eq!(grouping("8 / 4 / 2;"), "(Divide (Divide 8 4) 2)");
+        assert_eq!(grouping("1 + 2 * 3;"), "(Add 1 (Multiply 2 3))");
+        assert_eq!(grouping("1 * 2 + 3;"), "(Add (Multiply 1 2) 3)");
+        assert_eq!(grouping("(1 + 2) * 3;"), "(Multiply (Add 1 2) 3)");
+        assert_eq!(grouping("2 ^ 3 ^ 2;"), "(Exponent 2 (Exponent 3 2))");
+        assert_eq!(grouping("-1 - -2;"), "(Subtract (Negate 1) (Negate 2))");
+        assert_eq!(grouping("-a * b;"), "(Multiply (Negate a) b)");
+        assert_eq!(grouping("-2 ^ 2;"), "(Negate (Exponent 2 2))");
+        assert_eq!(grouping("a < b == c && d || e;"), "(Or (And (Equal (Less a b) c) d) e)");
+    }
+
+    // Reaching the limit on the last error in the file doesn't leave anything unreported
+    #[test]
+    fn error_limit_only_stops_early_with_input_left() {
+        for &(source, errors, stopped_early) in [("let = 1; let = 2;", 2, false),
+                                                 ("let = 1; let = 2; let = 3;", 2, true),
+                                                 ("let = 1;", 1, false)].iter() {
+            let mut parser = Parser::new(TokenStream::new(Scanner::new("test.txt", source)));
+            parser.set_error_limit(2);
+            assert_eq!(parser.start().unwrap_err().len(), errors);
+            assert_eq!(parser.stopped_early(), stopped_early);
+        }
+    }
 }
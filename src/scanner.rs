@@ -1,6 +1,12 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt;
+use span::Span as Span;
+use interner::Symbol as Symbol;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenType {
-    Variable,
+    Identifier,
     Terminator,
     Add,
     Subtract,
@@ -9,124 +15,794 @@ pub enum TokenType {
     Modulus,
     Exponent,
     EqualSign,
-    //Equality,
+    Equality,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+    Not,
+    BitAnd,
+    BitOr,
+    ShiftLeft,
+    ShiftRight,
+    AddAssign,
+    SubtractAssign,
+    MultiplyAssign,
+    DivideAssign,
+    Arrow,
+    Comma,
+    Colon,
+    Dot,
+    DotDot,
     ParenOpen,
     ParenClose,
     BraceOpen,
     BraceClose,
+    BracketOpen,
+    BracketClose,
     Integer,
-    FunctionCall,
-    Unknown
+    StringLiteral,
+    CharLiteral,
+    Label,
+    DocComment,
+    Let,
+    Mut,
+    Fn,
+    If,
+    Else,
+    While,
+    For,
+    In,
+    Break,
+    Continue,
+    Return,
+    True,
+    False,
+    Const,
+    Static,
+    Struct
 }
 
-#[derive(Debug, PartialEq, Clone)]
-pub struct Token {
-    t_type: TokenType,
-    t_val:  String
+// Reserved words, which can never be used as identifiers
+fn keyword(word: &str) -> Option<TokenType> {
+    match word {
+        "let"      => Some(TokenType::Let),
+        "mut"      => Some(TokenType::Mut),
+        "fn"       => Some(TokenType::Fn),
+        "if"       => Some(TokenType::If),
+        "else"     => Some(TokenType::Else),
+        "while"    => Some(TokenType::While),
+        "for"      => Some(TokenType::For),
+        "in"       => Some(TokenType::In),
+        "break"    => Some(TokenType::Break),
+        "continue" => Some(TokenType::Continue),
+        "return"   => Some(TokenType::Return),
+        "true"     => Some(TokenType::True),
+        "false"    => Some(TokenType::False),
+        "const"    => Some(TokenType::Const),
+        "static"   => Some(TokenType::Static),
+        "struct"   => Some(TokenType::Struct),
+        _          => None
+    }
 }
-impl Default for Token {
-    fn default() -> Token {
-        Token {t_type: TokenType::Unknown, t_val: String::new()}
+
+// How a token type is written, for error messages
+impl fmt::Display for TokenType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match *self {
+            TokenType::Identifier     => "identifier",
+            TokenType::Terminator     => "`;`",
+            TokenType::Add            => "`+`",
+            TokenType::Subtract       => "`-`",
+            TokenType::Multiply       => "`*`",
+            TokenType::Divide         => "`/`",
+            TokenType::Modulus        => "`%`",
+            TokenType::Exponent       => "`^`",
+            TokenType::EqualSign      => "`=`",
+            TokenType::Equality       => "`==`",
+            TokenType::NotEqual       => "`!=`",
+            TokenType::Less           => "`<`",
+            TokenType::LessEqual      => "`<=`",
+            TokenType::Greater        => "`>`",
+            TokenType::GreaterEqual   => "`>=`",
+            TokenType::And            => "`&&`",
+            TokenType::Or             => "`||`",
+            TokenType::Not            => "`!`",
+            TokenType::BitAnd         => "`&`",
+            TokenType::BitOr          => "`|`",
+            TokenType::ShiftLeft      => "`<<`",
+            TokenType::ShiftRight     => "`>>`",
+            TokenType::AddAssign      => "`+=`",
+            TokenType::SubtractAssign => "`-=`",
+            TokenType::MultiplyAssign => "`*=`",
+            TokenType::DivideAssign   => "`/=`",
+            TokenType::Arrow          => "`->`",
+            TokenType::Comma          => "`,`",
+            TokenType::Colon          => "`:`",
+            TokenType::Dot            => "`.`",
+            TokenType::DotDot         => "`..`",
+            TokenType::ParenOpen      => "`(`",
+            TokenType::ParenClose     => "`)`",
+            TokenType::BraceOpen      => "`{`",
+            TokenType::BraceClose     => "`}`",
+            TokenType::BracketOpen    => "`[`",
+            TokenType::BracketClose   => "`]`",
+            TokenType::Integer        => "integer literal",
+            TokenType::StringLiteral  => "string literal",
+            TokenType::CharLiteral    => "character literal",
+            TokenType::Label          => "loop label",
+            TokenType::DocComment     => "doc comment",
+            TokenType::Let            => "`let`",
+            TokenType::Mut            => "`mut`",
+            TokenType::Fn             => "`fn`",
+            TokenType::If             => "`if`",
+            TokenType::Else           => "`else`",
+            TokenType::While          => "`while`",
+            TokenType::For            => "`for`",
+            TokenType::In             => "`in`",
+            TokenType::Break          => "`break`",
+            TokenType::Continue       => "`continue`",
+            TokenType::Return         => "`return`",
+            TokenType::True           => "`true`",
+            TokenType::False          => "`false`",
+            TokenType::Const          => "`const`",
+            TokenType::Static         => "`static`",
+            TokenType::Struct         => "`struct`"
+        };
+        write!(f, "{}", text)
     }
 }
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Token {
+    t_type:   TokenType,
+    t_val:    Symbol,
+    t_suffix: Option<IntegerSuffix>,
+    t_span:   Span
+}
 impl Token {
     pub fn is_type(&self, token_type: TokenType) -> bool {
-        return self.t_type == token_type;
+        self.t_type == token_type
     }
-    pub fn get_val(&self) -> String {
-        return self.t_val.clone();
+    pub fn get_val(&self) -> Symbol {
+        self.t_val
     }
 
     pub fn get_type(&self) -> TokenType {
-        return self.t_type.clone();
+        self.t_type.clone()
+    }
+
+    pub fn get_span(&self) -> &Span {
+        &self.t_span
+    }
+
+    // Explicit type suffix of an integer literal, if it had one
+    #[allow(dead_code)]
+    pub fn get_suffix(&self) -> Option<IntegerSuffix> {
+        self.t_suffix
     }
 }
 
-pub fn scan(program: String) -> Vec<Token> {
+// Identifiers and integers name their value, everything else is just its type
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.t_type {
+            TokenType::Identifier => write!(f, "identifier `{}`", self.t_val),
+            TokenType::Integer    => write!(f, "integer `{}`", self.t_val),
+            _ => write!(f, "{}", self.t_type)
+        }
+    }
+}
 
-    let mut chars = program.chars().peekable();
-    let mut tokens = Vec::new();
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum IntegerSuffix {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64
+}
+impl IntegerSuffix {
+    fn from_str(suffix: &str) -> Option<IntegerSuffix> {
+        match suffix {
+            "i8"  => Some(IntegerSuffix::I8),
+            "i16" => Some(IntegerSuffix::I16),
+            "i32" => Some(IntegerSuffix::I32),
+            "i64" => Some(IntegerSuffix::I64),
+            "u8"  => Some(IntegerSuffix::U8),
+            "u16" => Some(IntegerSuffix::U16),
+            "u32" => Some(IntegerSuffix::U32),
+            "u64" => Some(IntegerSuffix::U64),
+            _     => None
+        }
+    }
 
-    while let Some(chr) = chars.next() {
-        let tok: Option<Token> = match chr {
+    // Largest literal that can be written with this suffix. Literals are never negative
+    // (the minus is a separate token), so only the upper bound matters. Every integer ends up
+    // in an i64, so a u64 can't go past i64::MAX either.
+    pub fn max_value(&self) -> u64 {
+        match *self {
+            IntegerSuffix::I8  => i8::MAX as u64,
+            IntegerSuffix::I16 => i16::MAX as u64,
+            IntegerSuffix::I32 => i32::MAX as u64,
+            IntegerSuffix::I64 => i64::MAX as u64,
+            IntegerSuffix::U8  => u8::MAX as u64,
+            IntegerSuffix::U16 => u16::MAX as u64,
+            IntegerSuffix::U32 => u32::MAX as u64,
+            IntegerSuffix::U64 => i64::MAX as u64
+        }
+    }
+}
 
-            x if x.is_alphabetic() => {
+#[derive(Debug, PartialEq, Clone)]
+pub enum LexErrorKind {
+    InvalidCharacter(char),
+    LiteralOutOfRange(String),
+    InvalidLiteral(String),
+    InvalidEscape(String),
+    InvalidChar(String),
+    Unterminated(&'static str)
+}
 
-                let mut var_string = String::new();
-                let mut sub_match = TokenType::Variable;
+#[derive(Debug, PartialEq, Clone)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub span: Span
+}
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            LexErrorKind::InvalidCharacter(chr) =>
+                write!(f, "{}: invalid character {:?}", self.span, chr),
+            LexErrorKind::LiteralOutOfRange(ref literal) =>
+                write!(f, "{}: integer literal {} is out of range", self.span, literal),
+            LexErrorKind::InvalidLiteral(ref literal) =>
+                write!(f, "{}: invalid integer literal {}", self.span, literal),
+            LexErrorKind::InvalidEscape(ref escape) =>
+                write!(f, "{}: invalid escape sequence {}", self.span, escape),
+            LexErrorKind::InvalidChar(ref literal) =>
+                write!(f, "{}: character literal '{}' must hold exactly one character", self.span, literal),
+            LexErrorKind::Unterminated(what) =>
+                write!(f, "{}: unterminated {}", self.span, what)
+        }
+    }
+}
 
-                var_string.push(chr);
+// Character stream over the source that keeps track of where in the file it is
+struct Cursor<'a> {
+    source: &'a str,
+    file:   Symbol,
+    offset: usize,
+    line:   usize,
+    column: usize
+}
+impl<'a> Cursor<'a> {
+    fn new(file: &str, program: &'a str) -> Cursor<'a> {
+        Cursor {source: program, file: Symbol::intern(file), offset: 0, line: 1, column: 1}
+    }
 
-                loop {
-                    match chars.peek() {
-                        Some(&next) => {
-                            match next {
-                                x if x.is_alphabetic() => {
-                                    var_string.push(next);
-                                    chars.next();
-                                },
-                                x if x == '(' => {
-                                    sub_match = TokenType::FunctionCall;
-                                    break
-                                }
-                                _ => break
-                            };
-                        },
-                        _ => break
-                    };
+    fn next(&mut self) -> Option<char> {
+        let chr = self.peek()?;
+
+        self.offset += chr.len_utf8();
+        if chr == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+
+        Some(chr)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source[self.offset..].chars().next()
+    }
+
+    // Source text from an earlier offset up to the current position
+    fn slice_from(&self, start: usize) -> &'a str {
+        &self.source[start..self.offset]
+    }
+
+    // Maximal munch for two-character operators: if the next character completes one of the
+    // longer operators, take it, otherwise the single character stands on its own
+    fn munch(&mut self, longer: &[(char, TokenType)], single: TokenType) -> TokenType {
+        if let Some(next) = self.peek() {
+            for &(second, ref token_type) in longer {
+                if next == second {
+                    self.next();
+                    return token_type.clone();
                 }
+            }
+        }
 
-                Some(Token{t_type: sub_match, t_val: var_string})
-            },
-            x if x.is_numeric() => {
-                let mut var_string = String::new();
-                var_string.push(chr);
+        single
+    }
+
+    // Span starting at an earlier mark and ending at the current position
+    fn span_from(&self, start: (usize, usize, usize)) -> Span {
+        let (offset, line, column) = start;
+        Span {file: self.file, start: offset, end: self.offset, line, column}
+    }
+
+    fn mark(&self) -> (usize, usize, usize) {
+        (self.offset, self.line, self.column)
+    }
+}
 
-                loop {
+// Lazily turns the source into tokens. Values are sliced straight out of the source where
+// possible, so the only per-token cost beyond scanning is interning them. Errors are yielded in
+// place of the offending token and scanning carries on after them.
+pub struct Scanner<'a> {
+    chars: Cursor<'a>
+}
+impl<'a> Scanner<'a> {
+    pub fn new(file: &str, program: &'a str) -> Scanner<'a> {
+        Scanner {chars: Cursor::new(file, program)}
+    }
+}
+impl<'a> Iterator for Scanner<'a> {
+    type Item = Result<Token, LexError>;
+
+    fn next(&mut self) -> Option<Result<Token, LexError>> {
+        let chars = &mut self.chars;
+
+        loop {
+            let start = chars.mark();
+            let chr = chars.next()?;
+
+            let mut suffix = None;
+            let tok: Result<Option<(TokenType, Cow<'a, str>)>, LexErrorKind> = match chr {
+
+                x if x.is_alphabetic() || x == '_' => {
+
+                    while let Some(next) = chars.peek() {
+                        match next {
+                            x if x.is_alphanumeric() || x == '_' => {
+                                chars.next();
+                            },
+                            _ => break
+                        };
+                    }
+
+                    let word = chars.slice_from(start.0);
+                    match keyword(word) {
+                        Some(word) => Ok(Some((word, Cow::Borrowed("")))),
+                        None => Ok(Some((TokenType::Identifier, Cow::Borrowed(word))))
+                    }
+                },
+                x if x.is_ascii_digit() => {
+                    match integer_literal(chr, chars) {
+                        Ok((value, int_suffix)) => {
+                            suffix = int_suffix;
+                            Ok(Some((TokenType::Integer, value)))
+                        },
+                        Err(e) => Err(e)
+                    }
+                },
+                '"' => string_literal(chars),
+                '\'' => {
+                    if starts_label(chars) { label(chars) } else { char_literal(chars) }
+                },
+                x if x.is_whitespace() => Ok(None),
+                ';' => Ok(Some((TokenType::Terminator, Cow::Borrowed("")))),
+                '+' => Ok(Some((chars.munch(&[('=', TokenType::AddAssign)], TokenType::Add), Cow::Borrowed("")))),
+                '-' => Ok(Some((chars.munch(&[('=', TokenType::SubtractAssign), ('>', TokenType::Arrow)], TokenType::Subtract), Cow::Borrowed("")))),
+                '*' => Ok(Some((chars.munch(&[('=', TokenType::MultiplyAssign)], TokenType::Multiply), Cow::Borrowed("")))),
+                '/' => {
                     match chars.peek() {
-                        Some(&next) => {
-                            match next {
-                                x if x.is_numeric() => {
-                                    var_string.push(next);
-                                    chars.next();
-                                },
-                                _ => break
-                            };
+                        Some('/') => {
+                            chars.next();
+                            line_comment(chars)
                         },
-                        _ => break
-                    };
+                        Some('*') => {
+                            chars.next();
+                            block_comment(chars)
+                        },
+                        _ => Ok(Some((chars.munch(&[('=', TokenType::DivideAssign)], TokenType::Divide), Cow::Borrowed(""))))
+                    }
+                },
+                '%' => Ok(Some((TokenType::Modulus, Cow::Borrowed("")))),
+                '^' => Ok(Some((TokenType::Exponent, Cow::Borrowed("")))),
+                '=' => Ok(Some((chars.munch(&[('=', TokenType::Equality)], TokenType::EqualSign), Cow::Borrowed("")))),
+                '!' => Ok(Some((chars.munch(&[('=', TokenType::NotEqual)], TokenType::Not), Cow::Borrowed("")))),
+                '<' => Ok(Some((chars.munch(&[('=', TokenType::LessEqual), ('<', TokenType::ShiftLeft)], TokenType::Less), Cow::Borrowed("")))),
+                '>' => Ok(Some((chars.munch(&[('=', TokenType::GreaterEqual), ('>', TokenType::ShiftRight)], TokenType::Greater), Cow::Borrowed("")))),
+                '&' => Ok(Some((chars.munch(&[('&', TokenType::And)], TokenType::BitAnd), Cow::Borrowed("")))),
+                '|' => Ok(Some((chars.munch(&[('|', TokenType::Or)], TokenType::BitOr), Cow::Borrowed("")))),
+                ',' => Ok(Some((TokenType::Comma, Cow::Borrowed("")))),
+                ':' => Ok(Some((TokenType::Colon, Cow::Borrowed("")))),
+                '.' => Ok(Some((chars.munch(&[('.', TokenType::DotDot)], TokenType::Dot), Cow::Borrowed("")))),
+                '(' => Ok(Some((TokenType::ParenOpen, Cow::Borrowed("")))),
+                ')' => Ok(Some((TokenType::ParenClose, Cow::Borrowed("")))),
+                '{' => Ok(Some((TokenType::BraceOpen, Cow::Borrowed("")))),
+                '}' => Ok(Some((TokenType::BraceClose, Cow::Borrowed("")))),
+                '[' => Ok(Some((TokenType::BracketOpen, Cow::Borrowed("")))),
+                ']' => Ok(Some((TokenType::BracketClose, Cow::Borrowed("")))),
+                _   => Err(LexErrorKind::InvalidCharacter(chr))
+            };
+
+            match tok {
+                Ok(Some((t_type, t_val))) => {
+                    // Punctuation has no value, no need to go through the interner for it
+                    let symbol = if t_val.is_empty() { Symbol::empty() } else { Symbol::intern(&t_val) };
+                    return Some(Ok(Token {t_type, t_val: symbol, t_suffix: suffix, t_span: chars.span_from(start)}));
+                },
+                Ok(None) => {},
+                Err(kind) => return Some(Err(LexError {kind, span: chars.span_from(start)}))
+            }
+        }
+    }
+}
+
+// Scan the whole program up front, collecting every lexical error rather than stopping at the first
+#[allow(dead_code)]
+pub fn scan(file: &str, program: &str) -> Result<Vec<Token>, Vec<LexError>> {
+    let mut tokens = Vec::new();
+    let mut errors = Vec::new();
+
+    for tok in Scanner::new(file, program) {
+        match tok {
+            Ok(t) => tokens.push(t),
+            Err(e) => errors.push(e)
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(tokens)
+    } else {
+        Err(errors)
+    }
+}
+
+// Bounded lookahead over a scanner for the parser. Doc comments are dropped here since only
+// tooling cares about them, and lexical errors are set aside to be reported once parsing is done.
+pub struct TokenStream<'a> {
+    scanner: Scanner<'a>,
+    buffer:  VecDeque<Token>,
+    errors:  Vec<LexError>
+}
+impl<'a> TokenStream<'a> {
+    pub fn new(scanner: Scanner<'a>) -> TokenStream<'a> {
+        TokenStream {scanner, buffer: VecDeque::new(), errors: Vec::new()}
+    }
+
+    // Make sure at least k + 1 tokens are buffered, returns false if the input runs out first
+    fn fill(&mut self, k: usize) -> bool {
+        while self.buffer.len() <= k {
+            match self.scanner.next() {
+                Some(Ok(t)) => {
+                    if !t.is_type(TokenType::DocComment) {
+                        self.buffer.push_back(t);
+                    }
+                },
+                Some(Err(e)) => self.errors.push(e),
+                None => return false
+            }
+        }
+
+        true
+    }
+
+    // The k-th token from the current position without consuming anything, 0 being the next one
+    pub fn peek(&mut self, k: usize) -> Option<&Token> {
+        if self.fill(k) {
+            self.buffer.get(k)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_errors(&self) -> &Vec<LexError> {
+        &self.errors
+    }
+}
+impl<'a> Iterator for TokenStream<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        self.fill(0);
+        self.buffer.pop_front()
+    }
+}
+
+
+// Called with the first digit consumed. Handles 0x/0o/0b prefixes, '_' separators and type
+// suffixes; the token value is always the plain decimal number.
+fn integer_literal<'a>(first: char, chars: &mut Cursor<'a>) -> Result<(Cow<'a, str>, Option<IntegerSuffix>), LexErrorKind> {
+    let start = chars.offset - first.len_utf8();
+
+    let mut radix = 10;
+    if first == '0' {
+        radix = match chars.peek() {
+            Some('x') => 16,
+            Some('o') => 8,
+            Some('b') => 2,
+            _ => 10
+        };
+        if radix != 10 {
+            chars.next();
+        }
+    }
+
+    let mut digits = String::new();
+    if radix == 10 {
+        digits.push(first);
+    }
+
+    // Digits of the radix and separators, then whatever letters and digits follow are the suffix
+    while let Some(next) = chars.peek() {
+        if next.is_digit(radix) || next == '_' {
+            if next != '_' {
+                digits.push(next);
+            }
+            chars.next();
+        } else {
+            break;
+        }
+    }
+
+    let suffix_start = chars.offset;
+    while let Some(next) = chars.peek() {
+        if next.is_alphanumeric() || next == '_' {
+            chars.next();
+        } else {
+            break;
+        }
+    }
+    let suffix_text = chars.slice_from(suffix_start);
+    let literal = chars.slice_from(start);
+
+    let suffix = if suffix_text.is_empty() {
+        None
+    } else {
+        match IntegerSuffix::from_str(suffix_text) {
+            Some(suffix) => Some(suffix),
+            None => return Err(LexErrorKind::InvalidLiteral(literal.to_string()))
+        }
+    };
+
+    if digits.is_empty() {
+        return Err(LexErrorKind::InvalidLiteral(literal.to_string()));
+    }
+
+    // Unsuffixed literals are i64
+    let max_value = suffix.unwrap_or(IntegerSuffix::I64).max_value();
+    match u64::from_str_radix(&digits, radix) {
+        Ok(value) if value <= max_value => {
+            // Plain decimal literals are already spelled the way the value is
+            let decimal = value.to_string();
+            if decimal == literal {
+                Ok((Cow::Borrowed(literal), suffix))
+            } else {
+                Ok((Cow::Owned(decimal), suffix))
+            }
+        },
+        _ => Err(LexErrorKind::LiteralOutOfRange(literal.to_string()))
+    }
+}
+
+// Called with the leading "//" consumed. "///" (but not "////") is kept as a doc comment token.
+fn line_comment<'a>(chars: &mut Cursor<'a>) -> Result<Option<(TokenType, Cow<'a, str>)>, LexErrorKind> {
+    let mut is_doc = false;
+    if chars.peek() == Some('/') {
+        chars.next();
+        is_doc = chars.peek() != Some('/');
+    }
+
+    let text_start = chars.offset;
+    while let Some(next) = chars.peek() {
+        if next == '\n' {
+            break;
+        }
+        chars.next();
+    }
+
+    if is_doc {
+        Ok(Some((TokenType::DocComment, Cow::Borrowed(chars.slice_from(text_start)))))
+    } else {
+        Ok(None)
+    }
+}
+
+// Called with the leading "/*" consumed. Block comments nest, so each "/*" needs its own "*/".
+fn block_comment<'a>(chars: &mut Cursor<'a>) -> Result<Option<(TokenType, Cow<'a, str>)>, LexErrorKind> {
+    let mut depth = 1;
+
+    while let Some(chr) = chars.next() {
+        match chr {
+            '/' if chars.peek() == Some('*') => {
+                chars.next();
+                depth += 1;
+            },
+            '*' if chars.peek() == Some('/') => {
+                chars.next();
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(None);
                 }
+            },
+            _ => {}
+        }
+    }
 
-                let num_opt = var_string.parse::<i64>().ok();
+    Err(LexErrorKind::Unterminated("block comment"))
+}
 
-                let num_val: i64 = match num_opt {
-                    Some(num_opt) => num_opt,
-                    None => 0
-                };
+// Called with the opening quote consumed. Scans through to the closing quote even after a bad
+// escape so the rest of the file still lexes; the first bad escape is what gets reported.
+// Until the first escape the value is just a slice of the source.
+fn string_literal<'a>(chars: &mut Cursor<'a>) -> Result<Option<(TokenType, Cow<'a, str>)>, LexErrorKind> {
+    let start = chars.offset;
+    let mut value: Option<String> = None;
+    let mut error = None;
 
-                Some(Token {t_type: TokenType::Integer, t_val: num_val.to_string()})
+    loop {
+        let before = chars.offset;
+        let chr = match chars.next() {
+            Some(chr) => chr,
+            None => break
+        };
+
+        match chr {
+            '"' => {
+                if let Some(e) = error {
+                    return Err(e);
+                }
+                return match value {
+                    Some(owned) => Ok(Some((TokenType::StringLiteral, Cow::Owned(owned)))),
+                    None => Ok(Some((TokenType::StringLiteral, Cow::Borrowed(&chars.source[start..before]))))
+                };
             },
-            ' ' | '\n'  => None,
-            ';' => Some(Token {t_type: TokenType::Terminator, ..Default::default()}),
-            '+' => Some(Token {t_type: TokenType::Add, ..Default::default()}),
-            '-' => Some(Token {t_type: TokenType::Subtract, ..Default::default()}),
-            '*' => Some(Token {t_type: TokenType::Multiply, ..Default::default()}),
-            '/' => Some(Token {t_type: TokenType::Divide, ..Default::default()}),
-            '%' => Some(Token {t_type: TokenType::Modulus, ..Default::default()}),
-            '^' => Some(Token {t_type: TokenType::Exponent, ..Default::default()}),
-            '=' => Some(Token {t_type: TokenType::EqualSign, ..Default::default()}), // todo: equality
-            '(' => Some(Token {t_type: TokenType::ParenOpen, ..Default::default()}),
-            ')' => Some(Token {t_type: TokenType::ParenClose, ..Default::default()}),
-            '{' => Some(Token {t_type: TokenType::BraceOpen, ..Default::default()}),
-            '}' => Some(Token {t_type: TokenType::BraceClose, ..Default::default()}),
-            _   => Some(Token {t_type: TokenType::Unknown, ..Default::default()})
+            '\\' => {
+                let mut owned = value.take().unwrap_or_else(|| chars.source[start..before].to_string());
+                match escape_sequence(chars, "string literal") {
+                    Ok(escaped) => owned.push(escaped),
+                    Err(e) => if error.is_none() { error = Some(e) }
+                }
+                value = Some(owned);
+            },
+            _ => if let Some(ref mut owned) = value { owned.push(chr) }
+        }
+    }
+
+    Err(LexErrorKind::Unterminated("string literal"))
+}
+
+// After a quote, an identifier that isn't immediately closed by another quote is a loop label
+// like 'outer rather than a char literal like 'a'
+fn starts_label(chars: &Cursor) -> bool {
+    let mut rest = chars.source[chars.offset..].chars();
+    match (rest.next(), rest.next()) {
+        (Some(first), second) => (first.is_alphabetic() || first == '_') && second != Some('\''),
+        _ => false
+    }
+}
+
+// Called with the quote consumed, the label's value is its name without the quote
+fn label<'a>(chars: &mut Cursor<'a>) -> Result<Option<(TokenType, Cow<'a, str>)>, LexErrorKind> {
+    let start = chars.offset;
+    while let Some(next) = chars.peek() {
+        if !(next.is_alphanumeric() || next == '_') {
+            break;
+        }
+        chars.next();
+    }
+
+    // 'ab' is a char literal with too much in it, not a label
+    if chars.peek() == Some('\'') {
+        chars.next();
+        return Err(LexErrorKind::InvalidChar(chars.source[start..chars.offset - 1].to_string()));
+    }
+
+    Ok(Some((TokenType::Label, Cow::Borrowed(chars.slice_from(start)))))
+}
+
+// Called with the opening quote consumed. Shares its escapes with string literals.
+fn char_literal<'a>(chars: &mut Cursor<'a>) -> Result<Option<(TokenType, Cow<'a, str>)>, LexErrorKind> {
+    let start = chars.offset;
+    let mut value = String::new();
+    let mut error = None;
+
+    loop {
+        let before = chars.offset;
+        let chr = match chars.next() {
+            Some(chr) => chr,
+            None => break
         };
 
-        if tok.is_some() {
-            tokens.push(tok.unwrap());
+        match chr {
+            '\'' => {
+                if let Some(e) = error {
+                    return Err(e);
+                }
+                if value.chars().count() != 1 {
+                    return Err(LexErrorKind::InvalidChar(chars.source[start..before].to_string()));
+                }
+                return Ok(Some((TokenType::CharLiteral, Cow::Owned(value))));
+            },
+            // A char literal never spans lines, so don't swallow the rest of the file looking for the quote
+            '\n' => break,
+            '\\' => {
+                match escape_sequence(chars, "character literal") {
+                    Ok(escaped) => value.push(escaped),
+                    Err(e) => if error.is_none() { error = Some(e) }
+                }
+            },
+            _ => value.push(chr)
         }
     }
 
-    return tokens;
+    Err(LexErrorKind::Unterminated("character literal"))
 }
 
+// Called with the backslash consumed, `what` names the enclosing literal
+fn escape_sequence(chars: &mut Cursor, what: &'static str) -> Result<char, LexErrorKind> {
+    let chr = match chars.next() {
+        Some(chr) => chr,
+        None => return Err(LexErrorKind::Unterminated(what))
+    };
+
+    match chr {
+        'n'  => Ok('\n'),
+        't'  => Ok('\t'),
+        'r'  => Ok('\r'),
+        '0'  => Ok('\0'),
+        '\\' => Ok('\\'),
+        '"'  => Ok('"'),
+        '\'' => Ok('\''),
+        'x'  => {
+            // Two hex digits, ASCII only so the byte written is the character
+            let mut digits = String::new();
+            for _ in 0..2 {
+                match chars.peek() {
+                    Some(d) if d.is_ascii_hexdigit() => {
+                        digits.push(d);
+                        chars.next();
+                    },
+                    _ => break
+                }
+            }
+
+            match u8::from_str_radix(&digits, 16) {
+                Ok(byte) if digits.len() == 2 && byte <= 0x7f => Ok(byte as char),
+                _ => Err(LexErrorKind::InvalidEscape(format!("\\x{}", digits)))
+            }
+        },
+        'u'  => {
+            // \u{...} with one to six hex digits naming a valid scalar value
+            if chars.peek() != Some('{') {
+                return Err(LexErrorKind::InvalidEscape("\\u".to_string()));
+            }
+            chars.next();
+
+            let mut digits = String::new();
+            loop {
+                match chars.peek() {
+                    Some('}') => {
+                        chars.next();
+                        break;
+                    },
+                    Some(d) if d.is_ascii_hexdigit() => {
+                        digits.push(d);
+                        chars.next();
+                    },
+                    _ => return Err(LexErrorKind::InvalidEscape(format!("\\u{{{}", digits)))
+                }
+            }
+
+            let code = if !digits.is_empty() && digits.len() <= 6 { u32::from_str_radix(&digits, 16).ok() } else { None };
+            match code.and_then(::std::char::from_u32) {
+                Some(escaped) => Ok(escaped),
+                None => Err(LexErrorKind::InvalidEscape(format!("\\u{{{}}}", digits)))
+            }
+        },
+        _ => Err(LexErrorKind::InvalidEscape(format!("\\{}", chr)))
+    }
+}
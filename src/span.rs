@@ -0,0 +1,37 @@
+use std::fmt;
+use interner::Symbol as Symbol;
+
+// A region of a source file. Offsets are in bytes, line and column are 1-based
+// and count characters, so they line up with what an editor shows.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Span {
+    pub file:   Symbol,
+    pub start:  usize,
+    pub end:    usize,
+    pub line:   usize,
+    pub column: usize
+}
+impl Default for Span {
+    fn default() -> Span {
+        Span {file: Symbol::empty(), start: 0, end: 0, line: 0, column: 0}
+    }
+}
+impl Span {
+    // Smallest span covering both self and other; line/column come from whichever starts first
+    pub fn to(&self, other: &Span) -> Span {
+        let first = if other.start < self.start { other } else { self };
+
+        Span {
+            file:   self.file,
+            start:  first.start,
+            end:    if other.end > self.end { other.end } else { self.end },
+            line:   first.line,
+            column: first.column
+        }
+    }
+}
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
@@ -1,68 +1,257 @@
 use std::collections::HashMap;
+use interner::Symbol as Symbol;
+use std::fmt;
 
-#[allow(dead_code)]
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum VariableType {
-    Integer
+    Integer,
+    Char,
+    Bool,
+    Array(u64),     // of i64, with this many elements
+    Struct(Symbol)
 }
+impl VariableType {
+    // Scalars are worked on in RAX, anything else by its address
+    pub fn is_scalar(&self) -> bool {
+        !matches!(*self, VariableType::Array(_) | VariableType::Struct(_))
+    }
+}
+// Types are written the way they are in the source
+impl fmt::Display for VariableType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VariableType::Integer => write!(f, "i64"),
+            VariableType::Char => write!(f, "char"),
+            VariableType::Bool => write!(f, "bool"),
+            VariableType::Array(length) => write!(f, "[i64; {}]", length),
+            VariableType::Struct(name) => write!(f, "{}", name)
+        }
+    }
+}
+
+// Sizes and alignments are the ones C uses on x86-64: i64 is a long, a char is a 32-bit code
+// point and a bool is a byte
+pub fn size_of(var_type: VariableType, structs: &Structs) -> u64 {
+    match var_type {
+        VariableType::Integer => 8,
+        VariableType::Char => 4,
+        VariableType::Bool => 1,
+        VariableType::Array(length) => 8 * length,
+        VariableType::Struct(name) => structs[&name].size
+    }
+}
+
+pub fn align_of(var_type: VariableType, structs: &Structs) -> u64 {
+    match var_type {
+        VariableType::Integer | VariableType::Array(_) => 8,
+        VariableType::Char => 4,
+        VariableType::Bool => 1,
+        VariableType::Struct(name) => structs[&name].align
+    }
+}
+
+fn round_up(value: u64, multiple: u64) -> u64 {
+    value.div_ceil(multiple) * multiple
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub name: Symbol,
+    pub var_type: VariableType,
+    pub offset: u64
+}
+
+// A struct laid out by the System V x86-64 ABI rules, so C sees the same thing: fields go in the
+// order they're declared, each at the next offset that's a multiple of its alignment. The struct
+// is as aligned as its most aligned field, and padded at the end to a multiple of that.
+#[derive(Debug, Clone)]
+pub struct StructLayout {
+    pub fields: Vec<Field>,
+    pub size: u64,
+    pub align: u64
+}
+impl StructLayout {
+    pub fn new(fields: &[(Symbol, VariableType)], structs: &Structs) -> StructLayout {
+        let mut laid_out = Vec::new();
+        let mut size = 0;
+        let mut align = 1;
+
+        for &(name, var_type) in fields {
+            let field_align = align_of(var_type, structs);
+            let offset = round_up(size, field_align);
+            laid_out.push(Field {name, var_type, offset});
 
-#[allow(dead_code)]
+            size = offset + size_of(var_type, structs);
+            if field_align > align {
+                align = field_align;
+            }
+        }
+
+        StructLayout {fields: laid_out, size: round_up(size, align), align}
+    }
+
+    pub fn field(&self, name: Symbol) -> Option<&Field> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+}
+
+pub type Structs = HashMap<Symbol, StructLayout>;
+
+// Names declared at the top level, visible everywhere. Constants are folded into the code that
+// uses them; statics have a fixed address in .data.
+#[derive(Debug, Clone, Copy)]
+pub enum Global {
+    Const(u64, VariableType),
+    Static {address: u64, var_type: VariableType, mutable: bool}
+}
+
+// Where a variable lives in the stack frame. Its index is its offset below the frame pointer,
+// so it occupies [rbp - index, rbp - index + length).
+#[derive(Debug, Clone, Copy)]
+struct Variable {
+    index: u64,
+    length: u64,
+    var_type: VariableType,
+    mutable: bool
+}
+
+// Variables in scope, innermost scope last. Leaving a scope hands its stack space back, so the
+// frame only has to be as big as the deepest point reached rather than every variable ever seen.
 #[derive(Debug)]
 pub struct SymbolTable {
-    data: Vec<u8>,
     length: u64,
-    indexes: HashMap<String, u64>,
-    lengths: HashMap<String, u64>,
-    types: HashMap<String, VariableType>,
-    symbol_section_address: u64
+    max_length: u64,
+    scopes: Vec<HashMap<Symbol, Variable>>,
+    scope_starts: Vec<u64>
 }
 impl SymbolTable {
-    #[allow(dead_code)]
     pub fn new() -> SymbolTable {
         SymbolTable {
-            data: Vec::new(),
             length: 0,
-            indexes: HashMap::new(),
-            lengths: HashMap::new(),
-            types: HashMap::new(),
-            symbol_section_address: 0x0
+            max_length: 0,
+            scopes: vec!(HashMap::new()),
+            scope_starts: vec!(0)
         }
     }
 
-    #[allow(dead_code)]
-    pub fn set_symbol_section_address(&mut self, address: u64) {
-        self.symbol_section_address = address;
+    pub fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+        self.scope_starts.push(self.length);
     }
 
-    #[allow(dead_code)]
-    pub fn insert(&mut self, var_name: &str, var_type: VariableType) {
-        let var_bytes = var_name.as_bytes();
-        let var_length = var_bytes.len() as u64;
-        let start_index = self.get_length();
-        self.data.extend_from_slice(var_bytes);
-        self.length += var_length;
-        self.indexes.insert(var_name.to_string(), start_index);
-        self.lengths.insert(var_name.to_string(), var_length);
-        self.types.insert(var_name.to_string(), var_type);
+    // Forget the innermost scope's variables and reuse their space
+    pub fn pop_scope(&mut self) {
+        self.scopes.pop();
+        self.length = self.scope_starts.pop().unwrap();
     }
 
-    #[allow(dead_code)]
-    pub fn get_length(&self) -> u64 {
+    // Declares the variable in the innermost scope, taking size bytes
+    pub fn insert(&mut self, var_name: Symbol, var_type: VariableType, size: u64, mutable: bool) -> u64 {
+        let index = self.insert_temporary(size);
+        let variable = Variable {index, length: round_up(size, 8), var_type, mutable};
+        self.scopes.last_mut().unwrap().insert(var_name, variable);
+
+        index
+    }
+
+    // Space in the innermost scope for a value with no name, such as an array literal. Slots are
+    // a multiple of 8 bytes so every one stays aligned.
+    pub fn insert_temporary(&mut self, size: u64) -> u64 {
+        self.length += round_up(size, 8);
+        if self.length > self.max_length {
+            self.max_length = self.length;
+        }
+
         self.length
     }
 
-    #[allow(dead_code)]
-    pub fn get_var_index(&self, var: &str) -> u64 {
-        *self.indexes.get(var).unwrap()
+    // Innermost variable with this name that's still in scope
+    fn lookup(&self, var: Symbol) -> Option<&Variable> {
+        self.scopes.iter().rev().filter_map(|scope| scope.get(&var)).next()
     }
 
-    #[allow(dead_code)]
-    pub fn get_var_length(&self, var: &str) -> u64 {
-        *self.lengths.get(var).unwrap()
+    pub fn contains(&self, var: Symbol) -> bool {
+        self.lookup(var).is_some()
+    }
+
+    // Whether the innermost scope already has a variable with this name
+    pub fn declared_in_scope(&self, var: Symbol) -> bool {
+        self.scopes.last().unwrap().contains_key(&var)
+    }
+
+    // Bytes of stack needed to hold every variable at the deepest point so far
+    pub fn get_length(&self) -> u64 {
+        self.max_length
+    }
+
+    pub fn get_var_index(&self, var: Symbol) -> u64 {
+        self.lookup(var).unwrap().index
     }
 
     #[allow(dead_code)]
-    pub fn get_var_type(&self, var: &str) -> &VariableType {
-        self.types.get(var).unwrap()
+    pub fn get_var_length(&self, var: Symbol) -> u64 {
+        self.lookup(var).unwrap().length
+    }
+
+    pub fn get_var_type(&self, var: Symbol) -> VariableType {
+        self.lookup(var).unwrap().var_type
     }
-}
\ No newline at end of file
+
+    pub fn is_mutable(&self, var: Symbol) -> bool {
+        self.lookup(var).unwrap().mutable
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StructLayout, Structs, VariableType};
+    use interner::Symbol as Symbol;
+    use std::collections::HashMap;
+
+    fn layout(fields: &[(&str, VariableType)], structs: &Structs) -> StructLayout {
+        let fields: Vec<(Symbol, VariableType)> = fields.iter().map(|&(name, t)| (Symbol::intern(name), t)).collect();
+        StructLayout::new(&fields, structs)
+    }
+
+    fn offsets(layout: &StructLayout) -> Vec<u64> {
+        layout.fields.iter().map(|f| f.offset).collect()
+    }
+
+    // Each field is aligned to its own size, and the whole struct to its widest field
+    #[test]
+    fn fields_are_padded_to_their_alignment() {
+        let structs = HashMap::new();
+        let l = layout(&[("a", VariableType::Char), ("b", VariableType::Integer),
+                         ("c", VariableType::Bool), ("d", VariableType::Char)], &structs);
+        assert_eq!(offsets(&l), vec![0, 8, 16, 20]);
+        assert_eq!((l.size, l.align), (24, 8));
+
+        let l = layout(&[("a", VariableType::Bool), ("b", VariableType::Bool), ("c", VariableType::Char)], &structs);
+        assert_eq!(offsets(&l), vec![0, 1, 4]);
+        assert_eq!((l.size, l.align), (8, 4));
+    }
+
+    #[test]
+    fn size_is_rounded_up_to_the_alignment() {
+        let structs = HashMap::new();
+        let l = layout(&[("a", VariableType::Integer), ("b", VariableType::Bool)], &structs);
+        assert_eq!(offsets(&l), vec![0, 8]);
+        assert_eq!((l.size, l.align), (16, 8));
+
+        let l = layout(&[], &structs);
+        assert_eq!((l.size, l.align), (0, 1));
+    }
+
+    #[test]
+    fn nested_structs_and_arrays() {
+        let mut structs = HashMap::new();
+        let inner = layout(&[("x", VariableType::Char), ("y", VariableType::Bool)], &structs);
+        assert_eq!((inner.size, inner.align), (8, 4));
+        structs.insert(Symbol::intern("Inner"), inner);
+
+        let l = layout(&[("a", VariableType::Bool), ("b", VariableType::Struct(Symbol::intern("Inner"))),
+                         ("c", VariableType::Array(3)), ("d", VariableType::Bool)], &structs);
+        assert_eq!(offsets(&l), vec![0, 4, 16, 40]);
+        assert_eq!((l.size, l.align), (48, 8));
+    }
+}
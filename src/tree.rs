@@ -2,47 +2,121 @@
 ///t.insert_l(Some("Y".to_string()));
 ///t.insert_r(Some("Z".to_string()));
 
+use span::Span as Span;
+use interner::Symbol as Symbol;
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulus,
+    Exponent,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+    Negate,
+    Not
+}
+impl Operator {
+    pub fn is_comparison(&self) -> bool {
+        matches!(*self, Operator::Equal | Operator::NotEqual | Operator::Less | Operator::LessEqual |
+                        Operator::Greater | Operator::GreaterEqual)
+    }
+}
+
+// Binary operators have their operands on the left and right, unary ones on the left only.
+// Blocks keep their statements in children. An if has its condition on the left, with the block
+// to run and then any else branch (another if, or a block) as children. A while has its condition
+// on the left and body as the only child; a for has the loop variable on the left and the range
+// start, range end and body as children. Loops, break and continue keep any label in val.
+// A let has the variable on the left, the value on the right, any type annotation as its only
+// child and whether it's mutable in the kind. Consts and statics are laid out the same way.
+// An array type annotation has its element type in val and its length as the only child.
+// Array literals keep their elements in children; [value; count] has the value on the left and
+// count on the right. Indexing has the array on the left and the index on the right.
+// A type annotation has the type's name in val.
+// A call has the callee on the left and its arguments as children.
+// A function definition has its name in val, the body on the left, any return type on the right
+// and its parameters as children, each a variable with any type annotation as its only child;
+// a return has the returned value, if any, on the left.
+// A struct definition has its name in val and its fields as children, laid out like parameters.
+// A struct literal has the struct's name in val and a Field for each field as children, with the
+// field's name in val and its value on the left. Reading a field has the struct on the left.
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum ASTNodeKind {
     Assignment,
+    Let(bool),
+    Const,
+    Static(bool),
+    Type,
     Variable,
     Integer,
-    FunctionCall
+    String,
+    Char,
+    Bool,
+    ArrayLiteral,
+    ArrayRepeat,
+    Index,
+    StructDef,
+    StructLiteral,
+    Field,
+    Block,
+    If,
+    While,
+    For,
+    Break,
+    Continue,
+    FunctionDef,
+    Return,
+    FunctionCall,
+    BinaryOp(Operator),
+    UnaryOp(Operator)
 }
 
+// A whole source file, one node per top level statement
+pub type Program = Vec<Node>;
+
 #[derive(Debug, Clone)]
 pub struct Node
 {
     pub kind: ASTNodeKind,
-    pub val:   Option<String>,
+    pub val:   Option<Symbol>,
+    pub span:  Span,
     pub left:  Option<Box<Node>>,
-    pub right: Option<Box<Node>>
+    pub right: Option<Box<Node>>,
+    pub children: Vec<Node>
 }
 #[allow(unused)]
 impl Node
 {
-    fn insert(&mut self, insert_left: bool, kind: ASTNodeKind, new_val: Option<String>)
+    fn insert(&mut self, insert_left: bool, kind: ASTNodeKind, new_val: Option<Symbol>, span: Span)
     {
         let target_node = if insert_left { &mut self.left } else { &mut self.right };
         match target_node {
-            &mut Some(ref mut subnode) => subnode.insert(insert_left, kind, new_val),
+            &mut Some(ref mut subnode) => subnode.insert(insert_left, kind, new_val, span),
             &mut None => {
-                let new_node = Node { kind:kind, val: new_val, left: None, right: None };
+                let new_node = Node { kind, val: new_val, span, left: None, right: None, children: Vec::new() };
                 let boxed_node = Some(Box::new(new_node));
                 *target_node = boxed_node;
             }
         }
     }
 
-    pub fn insert_l(&mut self, kind: ASTNodeKind, new_val: Option<String>)
+    pub fn insert_l(&mut self, kind: ASTNodeKind, new_val: Option<Symbol>, span: Span)
     {
-        self.insert(true, kind, new_val);
+        self.insert(true, kind, new_val, span);
     }
 
-    pub fn insert_r(&mut self, kind: ASTNodeKind, new_val: Option<String>)
+    pub fn insert_r(&mut self, kind: ASTNodeKind, new_val: Option<Symbol>, span: Span)
     {
-        self.insert(false, kind, new_val);
+        self.insert(false, kind, new_val, span);
     }
 
     fn append(&mut self, insert_left: bool, kind: ASTNodeKind, new_node: Node)
@@ -73,6 +147,11 @@ impl Node
         return self.kind;
     }
 
+    pub fn get_span(&self) -> &Span
+    {
+        &self.span
+    }
+
     pub fn has_val(&self) -> bool
     {
         return self.val.is_some();
@@ -119,11 +198,11 @@ impl Node
             res.push(node);
             match node.right {
                 None => {},
-                Some(box ref n) => stack.push(n)
+                Some(ref n) => stack.push(n)
             }
             match node.left {
                 None => {},
-                Some(box ref n) => stack.push(n)
+                Some(ref n) => stack.push(n)
             }
         }
 
@@ -140,11 +219,11 @@ impl Node
             let node = stack.pop().unwrap();
             match node.left {
                 None => {},
-                Some(box ref n) => stack.push(n)
+                Some(ref n) => stack.push(n)
             }
             match node.right {
                 None => {},
-                Some(box ref n) => stack.push(n)
+                Some(ref n) => stack.push(n)
             }
             res.push(node);
         }